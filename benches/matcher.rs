@@ -0,0 +1,79 @@
+//! Benchmark of matching and moving files in a large directory.
+//! Half of the files match the source pattern, so the benchmark covers both matching
+//! of rejected entries and capture extraction for moved ones.
+//!
+//! `plan` measures matching through the library API, without starting a process
+//! and without moving files.
+//!
+//! Run with `cargo bench --bench matcher`.
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use mmv::Rename;
+use std::fs::File;
+use std::process::Command;
+use tempdir::TempDir;
+
+const MMV: &str = env!("CARGO_BIN_EXE_mmv");
+
+/// Function creates a directory with `count` files, every second of them matches `log-*.txt`.
+fn create_directory(count: usize) -> TempDir {
+    let dir = TempDir::new("bench_dir").expect("failed to create bench_dir");
+    for i in 0..count {
+        let name = if i % 2 == 0 {
+            format!("log-{}.txt", i)
+        } else {
+            format!("other-{}.dat", i)
+        };
+        File::create(dir.path().join(name)).expect("failed to create file");
+    }
+    dir
+}
+
+fn bench_match_and_move(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match_and_move");
+    group.sample_size(10);
+
+    for count in [1_000, 10_000] {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter_batched(
+                || create_directory(count),
+                |dir| {
+                    let status = Command::new(MMV)
+                        .arg(dir.path().join("log-*.txt"))
+                        .arg(dir.path().join("moved-#1.txt"))
+                        .stdout(std::process::Stdio::null())
+                        .status()
+                        .expect("failed run mmv binary");
+                    assert!(status.success());
+                    dir
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_plan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("plan");
+    group.sample_size(10);
+
+    for count in [1_000, 10_000] {
+        let dir = create_directory(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &dir, |b, dir| {
+            b.iter(|| {
+                let plan = Rename::new()
+                    .pattern(
+                        dir.path().join("log-*.txt"),
+                        dir.path().join("moved-#1.txt"),
+                    )
+                    .plan()
+                    .expect("failed plan moves");
+                assert_eq!(plan.pairs().len(), count / 2);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_match_and_move, bench_plan);
+criterion_main!(benches);
@@ -0,0 +1,208 @@
+//! Module runs the command line application: it turns parsed arguments into a batch of moves.
+//! It is used by the `mmv` binary and is not a part of the stable library API.
+use crate::cli::parser::Args;
+use crate::config::Config;
+use crate::encoding;
+use crate::errors::MassMoveError;
+use crate::operations::conflict::ConflictResolver;
+use crate::operations::edit::edit_file_list;
+use crate::operations::file_move::{FilesMover, MoveFiles};
+use crate::operations::pair_list::read_pairs_from_path;
+use crate::operations::plan::{Plan, PlanPattern};
+use crate::output::{MoveStatus, Reporter};
+use crate::rename::{build_matcher, plan_pair};
+use std::path::Path;
+
+/// Function collects the error in `--keep-going` mode and returns it otherwise.
+fn keep_going(
+    config: &Config,
+    errors: &mut Vec<MassMoveError>,
+    err: MassMoveError,
+) -> Result<(), MassMoveError> {
+    if !config.keep_going() {
+        return Err(err);
+    }
+    errors.push(err);
+    Ok(())
+}
+
+/// Function checks with `--strict-utf8` that paths are valid UTF-8.
+/// An invalid path is reported as failed and its error is collected in `--keep-going` mode.
+/// It returns false if the path is invalid.
+fn check_utf8(
+    config: &Config,
+    reporter: &Reporter,
+    errors: &mut Vec<MassMoveError>,
+    paths: &[&Path],
+) -> Result<bool, MassMoveError> {
+    if !config.strict_utf8() {
+        return Ok(true);
+    }
+    match paths.iter().try_for_each(|path| encoding::check_utf8(path)) {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            reporter.report(paths[0], None, MoveStatus::Failed, Some(&err));
+            keep_going(config, errors, err)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Function returns the result of the batch. In `--keep-going` mode it is aggregated
+/// with errors collected before the batch.
+fn finish(
+    is_keep_going: bool,
+    mut errors: Vec<MassMoveError>,
+    result: Result<(), MassMoveError>,
+    reporter: &Reporter,
+) -> Result<(), MassMoveError> {
+    if !is_keep_going {
+        return result;
+    }
+
+    if let Err(err) = result {
+        errors.push(err);
+    }
+    MassMoveError::from_errors(errors)
+        .map_err(|err| MassMoveError::Incomplete(Box::new(err), reporter.count(MoveStatus::Moved)))
+}
+
+/// Function runs `--edit` mode: matched files are edited in a text editor,
+/// changed lines are moved and emptied lines are deleted as one batch.
+fn run_edit(args: Args, config: Config, reporter: &Reporter) -> Result<(), MassMoveError> {
+    let mut errors = Vec::new();
+    let mut files = Vec::new();
+    for source_path in args.edit_sources()? {
+        let matcher = match build_matcher(source_path, &config, reporter) {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                keep_going(&config, &mut errors, err)?;
+                continue;
+            }
+        };
+        let files_with_matches = match matcher.iter_files_with_matches() {
+            Ok(files_with_matches) => files_with_matches,
+            Err(err) => {
+                keep_going(&config, &mut errors, err)?;
+                continue;
+            }
+        };
+        for file_with_match in files_with_matches {
+            match file_with_match {
+                Ok(file_with_match) => {
+                    let file = file_with_match.filename();
+                    if check_utf8(&config, reporter, &mut errors, &[file])? {
+                        files.push(file.to_owned());
+                    }
+                }
+                Err(err) => keep_going(&config, &mut errors, err)?,
+            }
+        }
+    }
+    if files.is_empty() {
+        return finish(config.keep_going(), errors, Ok(()), reporter);
+    }
+
+    let edited = edit_file_list(&files, config.editor(), config.allow_delete())?;
+    let mut files_to_move = Vec::new();
+    for file_pair in edited.files_to_move {
+        if check_utf8(
+            &config,
+            reporter,
+            &mut errors,
+            &[&file_pair.from, &file_pair.to],
+        )? {
+            files_to_move.push(file_pair);
+        }
+    }
+
+    let is_keep_going = config.keep_going();
+    let result = FilesMover::new(config, files_to_move)
+        .with_deletions(edited.files_to_delete)
+        .run(reporter);
+    finish(is_keep_going, errors, result, reporter)
+}
+
+/// Function is wrapper for main function.
+/// It help separate logic from main function. Moreove, it make easier to handling errors in main function.
+/// All pairs of source and target paths (and pairs from `--from-file`) are planned first
+/// and then moved as one batch. With `--plan-out` the validated batch is written to a plan file,
+/// with `--apply` the batch is read from a plan file.
+pub fn run(args: Args, config: Config, reporter: &Reporter) -> Result<(), MassMoveError> {
+    if args.edit() {
+        return run_edit(args, config, reporter);
+    }
+    if let Some(plan_path) = args.apply() {
+        let plan = Plan::read_from(&config, plan_path)?;
+        let on_duplicate = plan.on_duplicate();
+        let resolution = plan.into_resolution(&config)?;
+        let config = match on_duplicate {
+            Some(on_duplicate) => config.with_on_duplicate(on_duplicate),
+            None => config,
+        };
+        return FilesMover::new(config, resolution.files_to_move)
+            .with_duplicates(resolution.duplicates)
+            .run(reporter);
+    }
+    let pairs = args.pairs()?;
+    let patterns: Vec<PlanPattern> = match args.plan_out() {
+        Some(_) => pairs
+            .iter()
+            .map(|(source_path, target_path)| {
+                PlanPattern::from_os_str(source_path.as_os_str(), target_path)
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+
+    let mut errors = Vec::new();
+    let mut files_to_move: Vec<MoveFiles> = Vec::new();
+    for (source_path, target_path) in pairs {
+        match plan_pair(source_path, target_path, &config, reporter, &mut errors) {
+            Ok(planned) => files_to_move.extend(planned),
+            Err(err) => keep_going(&config, &mut errors, err)?,
+        }
+    }
+
+    if let Some(path) = args.pair_list_path() {
+        let pair_list = match read_pairs_from_path(path, args.null()) {
+            Ok(pair_list) => pair_list,
+            Err(err) => {
+                keep_going(&config, &mut errors, err)?;
+                Vec::new()
+            }
+        };
+        for file_pair in pair_list {
+            if check_utf8(
+                &config,
+                reporter,
+                &mut errors,
+                &[&file_pair.from, &file_pair.to],
+            )? {
+                files_to_move.push(file_pair);
+            }
+        }
+    }
+
+    let resolution = ConflictResolver::new(&config)?.resolve(files_to_move, reporter);
+    let is_keep_going = config.keep_going();
+    let result = match args.plan_out() {
+        None => FilesMover::new(config, resolution.files_to_move)
+            .with_duplicates(resolution.duplicates)
+            .run(reporter),
+        Some(plan_path) => {
+            let plan = Plan::new(
+                &config,
+                patterns,
+                args.pair_list_path(),
+                &resolution.files_to_move,
+            )?
+            .with_duplicates(&config, &resolution.duplicates)?;
+            FilesMover::new(config.clone(), resolution.files_to_move)
+                .with_duplicates(resolution.duplicates)
+                .run(reporter)
+                .and_then(|_| plan.write_to(&config, plan_path))
+        }
+    };
+    finish(is_keep_going, errors, result, reporter)
+}
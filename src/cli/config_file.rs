@@ -0,0 +1,385 @@
+//! Module reads configuration files: defaults of options and named presets.
+//! The user config file is `$XDG_CONFIG_HOME/mmv/config.toml` (`~/.config/mmv/config.toml`),
+//! the project config file is `.mmv.toml` in the current directory or its nearest parent,
+//! it can not set `editor`, `force` and `on-duplicate`.
+//! Values of the project file override values of the user file, values of a preset override
+//! both and command line flags override everything.
+//!
+//! Example of a config file:
+//! ```toml
+//! sort = "name"
+//! ignore-case = true
+//! exclude = ["*.tmp"]
+//!
+//! [presets.photos]
+//! target = "./photos/#1.#2"
+//! jobs = 4
+//! ```
+use super::parser::Args;
+use crate::errors::{MassMoveError, Operation};
+use crate::operations::conflict::{OnConflict, OnDuplicate};
+use crate::output::{ColorChoice, OutputFormat};
+use crate::rename::SortOrder;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the project config file.
+pub const PROJECT_CONFIG_FILE: &str = ".mmv.toml";
+
+/// `Settings` are defaults of options, they are read from a config file or a preset.
+/// Keys are named as the command line flags, unset values are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Settings {
+    pub force: Option<bool>,
+    pub on_conflict: Option<OnConflict>,
+    pub on_duplicate: Option<OnDuplicate>,
+    pub suffix_format: Option<String>,
+    pub create_dirs: Option<bool>,
+    pub jobs: Option<usize>,
+    pub exclude: Vec<String>,
+    pub ignore_files: Option<bool>,
+    pub ignore_case: Option<bool>,
+    pub sort: Option<SortOrder>,
+    pub strict_utf8: Option<bool>,
+    pub keep_going: Option<bool>,
+    pub allow_no_match: Option<bool>,
+    pub output: Option<OutputFormat>,
+    pub color: Option<ColorChoice>,
+    pub progress: Option<bool>,
+    pub verify: Option<bool>,
+    pub editor: Option<String>,
+}
+
+/// `Preset` is a named bundle of patterns and settings, it is chosen with `--preset NAME`.
+/// With `target` all positional arguments are SOURCE paths moved to the target pattern,
+/// `pairs` are added as `-e SOURCE TARGET` pairs.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Preset {
+    pub target: Option<String>,
+    pub pairs: Vec<(String, String)>,
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Keys that are not settings, they are reported as errors.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+/// `ConfigFile` is the content of a config file: settings and presets.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    pub presets: BTreeMap<String, Preset>,
+    #[serde(flatten)]
+    pub settings: Settings,
+    /// Keys that are not settings, they are reported as errors.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl Settings {
+    /// Function overrides the settings with values set in `other`, exclude patterns are added.
+    fn merge(mut self, other: Settings) -> Self {
+        self.force = other.force.or(self.force);
+        self.on_conflict = other.on_conflict.or(self.on_conflict);
+        self.on_duplicate = other.on_duplicate.or(self.on_duplicate);
+        self.suffix_format = other.suffix_format.or(self.suffix_format);
+        self.create_dirs = other.create_dirs.or(self.create_dirs);
+        self.jobs = other.jobs.or(self.jobs);
+        self.exclude.extend(other.exclude);
+        self.ignore_files = other.ignore_files.or(self.ignore_files);
+        self.ignore_case = other.ignore_case.or(self.ignore_case);
+        self.sort = other.sort.or(self.sort);
+        self.strict_utf8 = other.strict_utf8.or(self.strict_utf8);
+        self.keep_going = other.keep_going.or(self.keep_going);
+        self.allow_no_match = other.allow_no_match.or(self.allow_no_match);
+        self.output = other.output.or(self.output);
+        self.color = other.color.or(self.color);
+        self.progress = other.progress.or(self.progress);
+        self.verify = other.verify.or(self.verify);
+        self.editor = other.editor.or(self.editor);
+        self
+    }
+}
+
+impl ConfigFile {
+    /// Function parses a config file, `path` is used in error messages.
+    /// Unknown keys are errors, so misspelled settings are not ignored.
+    pub fn parse(content: &str, path: &Path) -> Result<Self, MassMoveError> {
+        let invalid = |message: String| {
+            MassMoveError::InvalidConfig(format!("{}: {}", path.display(), message))
+        };
+        let config_file: ConfigFile =
+            toml::from_str(content).map_err(|err| invalid(err.to_string()))?;
+
+        let unknown =
+            config_file
+                .unknown
+                .keys()
+                .cloned()
+                .chain(config_file.presets.iter().flat_map(|(name, preset)| {
+                    preset
+                        .unknown
+                        .keys()
+                        .map(move |key| format!("presets.{}.{}", name, key))
+                }));
+        if let Some(key) = unknown.into_iter().next() {
+            return Err(invalid(format!("unknown key `{}`", key)));
+        }
+        Ok(config_file)
+    }
+
+    /// Function reads a config file, a missing file is `None`.
+    pub fn read(path: &Path) -> Result<Option<Self>, MassMoveError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content, path).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(MassMoveError::Io {
+                operation: Operation::ReadFile,
+                path: path.to_owned(),
+                source,
+            }),
+        }
+    }
+
+    /// Function checks a project config file: it may come from an untrusted checkout,
+    /// so it can not set a command to run or destructive defaults. `editor`, `force`
+    /// and `on-duplicate` are allowed only in the user config file.
+    pub fn check_project(&self, path: &Path) -> Result<(), MassMoveError> {
+        let sections = std::iter::once((String::new(), &self.settings)).chain(
+            self.presets
+                .iter()
+                .map(|(name, preset)| (format!("presets.{}.", name), &preset.settings)),
+        );
+        for (prefix, settings) in sections {
+            let keys = [
+                ("editor", settings.editor.is_some()),
+                ("force", settings.force.is_some()),
+                ("on-duplicate", settings.on_duplicate.is_some()),
+            ];
+            if let Some((key, _)) = keys.iter().find(|(_, is_set)| *is_set) {
+                return Err(MassMoveError::InvalidConfig(format!(
+                    "{}: `{}{}` is allowed only in the user config file",
+                    path.display(),
+                    prefix,
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Function overrides settings and presets with the ones of `other`.
+    fn merge(mut self, other: ConfigFile) -> Self {
+        self.settings = self.settings.merge(other.settings);
+        self.presets.extend(other.presets);
+        self
+    }
+
+    /// Function returns the settings with the preset applied and the preset itself.
+    pub fn select(
+        mut self,
+        preset: Option<&str>,
+    ) -> Result<(Settings, Option<Preset>), MassMoveError> {
+        let Some(name) = preset else {
+            return Ok((self.settings, None));
+        };
+        let Some(preset) = self.presets.remove(name) else {
+            return Err(MassMoveError::InvalidArguments(format!(
+                "unknown preset: {}",
+                name
+            )));
+        };
+        let settings = self.settings.merge(preset.settings.clone());
+        Ok((settings, Some(preset)))
+    }
+}
+
+/// Function returns the path of the user config file: `$XDG_CONFIG_HOME/mmv/config.toml`
+/// or `$HOME/.config/mmv/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("mmv").join("config.toml"))
+}
+
+/// Function finds the project config file in the directory or its nearest parent.
+pub fn project_config_path(directory: &Path) -> Option<PathBuf> {
+    directory
+        .ancestors()
+        .map(|directory| directory.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Function loads config files for the arguments: the user config file (or `--config PATH`)
+/// and the project config file. With `--no-config` no files are read.
+pub fn load(args: &Args) -> Result<ConfigFile, MassMoveError> {
+    let mut config_file = ConfigFile::default();
+    if args.no_config() {
+        return Ok(config_file);
+    }
+
+    if let Some(path) = args.config() {
+        let Some(user) = ConfigFile::read(path)? else {
+            return Err(MassMoveError::InvalidConfig(format!(
+                "{}: file not found",
+                path.display()
+            )));
+        };
+        config_file = config_file.merge(user);
+    } else if let Some(user) = user_config_path()
+        .map(|path| ConfigFile::read(&path))
+        .transpose()?
+        .flatten()
+    {
+        config_file = config_file.merge(user);
+    }
+
+    let current_directory = env::current_dir().map_err(|source| MassMoveError::Io {
+        operation: Operation::ReadDirectory,
+        path: PathBuf::from("."),
+        source,
+    })?;
+    if let Some(path) = project_config_path(&current_directory) {
+        if let Some(project) = ConfigFile::read(&path)? {
+            project.check_project(&path)?;
+            config_file = config_file.merge(project);
+        }
+    }
+    Ok(config_file)
+}
+
+/// Function loads config files and applies the preset chosen with `--preset` to the arguments.
+/// It returns the arguments with patterns of the preset and the settings that are used
+/// for options not given on the command line.
+pub fn resolve(args: Args) -> Result<(Args, Settings), MassMoveError> {
+    let (settings, preset) = load(&args)?.select(args.preset())?;
+    let args = match preset {
+        Some(preset) => args.with_preset(&preset)?,
+        None => args,
+    };
+    Ok((args, settings))
+}
+
+#[test]
+fn test_select_preset() {
+    let user = ConfigFile::parse(
+        r#"
+        sort = "name"
+        exclude = ["*.tmp"]
+        jobs = 2
+
+        [presets.photos]
+        target = "./photos/#1.jpg"
+        jobs = 4
+        "#,
+        Path::new("config.toml"),
+    )
+    .unwrap();
+    let project = ConfigFile::parse(
+        r#"
+        ignore-case = true
+        exclude = ["*.bak"]
+
+        [presets.logs]
+        pairs = [["./*.log", "./logs/#1.log"]]
+        keep-going = true
+        "#,
+        Path::new(".mmv.toml"),
+    )
+    .unwrap();
+    let config_file = user.merge(project);
+
+    type Case<'a> = (Option<&'a str>, Option<Settings>);
+    let cases: Vec<Case> = vec![
+        (
+            None,
+            Some(Settings {
+                jobs: Some(2),
+                exclude: vec!["*.tmp".to_owned(), "*.bak".to_owned()],
+                ignore_case: Some(true),
+                sort: Some(SortOrder::Name),
+                ..Settings::default()
+            }),
+        ),
+        (
+            Some("photos"),
+            Some(Settings {
+                jobs: Some(4),
+                exclude: vec!["*.tmp".to_owned(), "*.bak".to_owned()],
+                ignore_case: Some(true),
+                sort: Some(SortOrder::Name),
+                ..Settings::default()
+            }),
+        ),
+        (
+            Some("logs"),
+            Some(Settings {
+                jobs: Some(2),
+                exclude: vec!["*.tmp".to_owned(), "*.bak".to_owned()],
+                ignore_case: Some(true),
+                sort: Some(SortOrder::Name),
+                keep_going: Some(true),
+                ..Settings::default()
+            }),
+        ),
+        (Some("music"), None),
+    ];
+
+    for case in cases {
+        let settings = config_file
+            .clone()
+            .select(case.0)
+            .ok()
+            .map(|(settings, _)| settings);
+        assert_eq!(settings, case.1, "preset: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_parse_invalid_config() {
+    let cases: Vec<&str> = vec![
+        "forse = true",
+        "jobs = \"four\"",
+        "sort = \"size\"",
+        "[presets.photos]\ntraget = \"#1\"",
+    ];
+
+    for case in cases {
+        let result = ConfigFile::parse(case, Path::new("config.toml"));
+        assert!(
+            matches!(result, Err(MassMoveError::InvalidConfig(_))),
+            "config: {}",
+            case
+        );
+    }
+}
+
+#[test]
+fn test_check_project() {
+    let cases: Vec<(&str, bool)> = vec![
+        ("sort = \"name\"\nkeep-going = true", true),
+        ("editor = \"sh -c 'rm -rf ~'\"", false),
+        ("force = true", false),
+        ("on-duplicate = \"delete-source\"", false),
+        ("[presets.photos]\nforce = false", false),
+    ];
+
+    for case in cases {
+        let path = Path::new(PROJECT_CONFIG_FILE);
+        let config_file = ConfigFile::parse(case.0, path).unwrap();
+        assert_eq!(
+            config_file.check_project(path).is_ok(),
+            case.1,
+            "config: {}",
+            case.0
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Module generates shell completions and the man page from the `Args` definition,
+//! so they always document the same options as the parser.
+use super::parser::{Args, Command};
+use crate::errors::{
+    EX_CANTCREAT, EX_CONFIG, EX_DATAERR, EX_INCOMPLETE, EX_IOERR, EX_NOINPUT, EX_NOPERM,
+    EX_NO_MATCH, EX_TEMPFAIL, EX_UNAVAILABLE, EX_USAGE,
+};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use clap_mangen::Man;
+use roff::{bold, italic, roman, Roff};
+use std::io::{self, Write};
+
+/// Exit codes of the process with their meaning, they are documented in the man page.
+const EXIT_CODES: &[(i32, &str)] = &[
+    (0, "Success."),
+    (
+        EX_NO_MATCH,
+        "No files matched for a SOURCE pattern (success with --allow-no-match).",
+    ),
+    (
+        EX_INCOMPLETE,
+        "Errors in --keep-going mode, other files are moved.",
+    ),
+    (EX_USAGE, "Invalid arguments or patterns."),
+    (
+        EX_DATAERR,
+        "Invalid pair list, edited list, plan or non-UTF-8 path with --strict-utf8.",
+    ),
+    (EX_NOINPUT, "Source directory not found."),
+    (EX_UNAVAILABLE, "Editor failed."),
+    (
+        EX_CANTCREAT,
+        "Conflicts: existing target, collision, duplicate source or cycle.",
+    ),
+    (EX_IOERR, "A file could not be moved or rolled back."),
+    (EX_TEMPFAIL, "Files changed since the plan (--apply)."),
+    (EX_NOPERM, "Permission denied."),
+    (EX_CONFIG, "Invalid config file."),
+];
+
+/// Function runs a command: prints a completion script or the man page to stdout.
+/// The output is built in memory first, a closed pipe (e.g. `mmv man | head`) is not an error.
+pub fn run(command: Command) -> io::Result<()> {
+    let mut output = Vec::new();
+    match command {
+        Command::Completions { shell } => write_completions(shell, &mut output),
+        Command::Man => write_man_page(&mut output)?,
+    }
+
+    let mut stdout = io::stdout().lock();
+    match stdout.write_all(&output).and_then(|_| stdout.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Function writes a completion script of the shell.
+/// `clap_complete` panics on write errors, so it should write to memory.
+pub fn write_completions(shell: Shell, out: &mut Vec<u8>) {
+    let mut command = Args::command();
+    let name = command.get_name().to_owned();
+    clap_complete::generate(shell, &mut command, name, out);
+}
+
+/// Function writes the man page in the roff format.
+/// Sections generated from `Args` are followed by sections on pattern and template syntax,
+/// exit codes and config files.
+pub fn write_man_page(out: &mut dyn Write) -> io::Result<()> {
+    let man = Man::new(Args::command());
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    man.render_subcommands_section(out)?;
+    out.write_all(extra_sections().render().as_bytes())?;
+    man.render_version_section(out)
+}
+
+/// Function builds sections of the man page that are not a part of `Args`.
+fn extra_sections() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["PATTERNS"]).text([
+        roman("A SOURCE is a path whose file name may contain "),
+        bold("*"),
+        roman(", it matches any part of a name without a dot. Only files of the directory are matched, \
+               not subdirectories. The name is matched as raw bytes, so names that are not valid \
+               UTF-8 are supported."),
+    ]);
+
+    roff.control("SH", ["TEMPLATES"]).text([
+        roman("A TARGET is a path where "),
+        bold("#1"),
+        roman(", "),
+        bold("#2"),
+        roman(", ... are replaced with the parts matched by the first, second, ... "),
+        bold("*"),
+        roman(" of the SOURCE. For example, "),
+        italic("'notes/2024-*.txt' 'archive/#1.md'"),
+        roman(" moves notes/2024-10.txt to archive/10.md."),
+    ]);
+    roff.control("PP", []).text([
+        roman("A TARGET may also use placeholders of the file: "),
+        bold("#{sha256}"),
+        roman(", "),
+        bold("#{md5}"),
+        roman(" and "),
+        bold("#{blake3}"),
+        roman(" are lowercase hex hashes of the content, "),
+        bold("#{sha256:N}"),
+        roman(" is the first N hex digits."),
+    ]);
+    roff.control("PP", []).text([
+        bold("#{date}"),
+        roman(" is the EXIF capture date of an image or its modification time, formatted with "),
+        italic("strftime"),
+        roman(" specifiers ("),
+        bold("#{date:%Y-%m-%d}"),
+        roman(" by default). "),
+        bold("#{camera}"),
+        roman(", "),
+        bold("#{make}"),
+        roman(", "),
+        bold("#{lens}"),
+        roman(", "),
+        bold("#{width}"),
+        roman(" and "),
+        bold("#{height}"),
+        roman(" are EXIF fields, a missing field is an error unless a default is given: "),
+        bold("#{lens:unknown}"),
+        roman("."),
+    ]);
+    roff.control("PP", []).text([
+        bold("#{artist}"),
+        roman(", "),
+        bold("#{album}"),
+        roman(", "),
+        bold("#{title}"),
+        roman(", "),
+        bold("#{genre}"),
+        roman(", "),
+        bold("#{track}"),
+        roman(", "),
+        bold("#{disc}"),
+        roman(" and "),
+        bold("#{year}"),
+        roman(" are audio tags. Numbers are zero-padded to the width of the argument ("),
+        bold("#{track:02}"),
+        roman("), text tags take a default value ("),
+        bold("#{album:Singles}"),
+        roman(") and are sanitized into valid file names."),
+    ]);
+
+    roff.control("SH", ["EXIT STATUS"]);
+    for (code, meaning) in EXIT_CODES {
+        roff.control("TP", [])
+            .text([bold(code.to_string())])
+            .text([roman(*meaning)]);
+    }
+
+    roff.control("SH", ["FILES"])
+        .control("TP", [])
+        .text([italic("~/.config/mmv/config.toml")])
+        .text([roman(
+            "User config file with defaults of options and presets ($XDG_CONFIG_HOME/mmv/config.toml).",
+        )])
+        .control("TP", [])
+        .text([italic(".mmv.toml")])
+        .text([roman(
+            "Project config file in the current directory or its nearest parent, it overrides the user config file.",
+        )]);
+    roff
+}
+
+#[test]
+fn test_write_man_page() {
+    let mut page = Vec::new();
+    write_man_page(&mut page).unwrap();
+    let page = String::from_utf8(page).unwrap();
+
+    let sections = [
+        ".SH NAME",
+        ".SH SYNOPSIS",
+        ".SH OPTIONS",
+        ".SH PATTERNS",
+        ".SH TEMPLATES",
+        ".SH \"EXIT STATUS\"",
+        ".SH FILES",
+    ];
+    for section in sections {
+        assert!(page.contains(section), "section: {}", section);
+    }
+
+    let placeholders = [
+        "#{sha256:N}",
+        "#{blake3}",
+        "#{date}",
+        "#{lens:unknown}",
+        "#{track:02}",
+    ];
+    for placeholder in placeholders {
+        assert!(page.contains(placeholder), "placeholder: {}", placeholder);
+    }
+}
+
+#[test]
+fn test_write_completions() {
+    let cases: Vec<(Shell, &str)> = vec![
+        (Shell::Bash, "_mmv()"),
+        (Shell::Zsh, "#compdef mmv"),
+        (Shell::Fish, "complete -c mmv"),
+        (Shell::Elvish, "edit:completion:arg-completer[mmv]"),
+    ];
+
+    for case in cases {
+        let mut script = Vec::new();
+        write_completions(case.0, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains(case.1), "shell: {}", case.0);
+        assert!(script.contains("keep-going"), "shell: {}", case.0);
+    }
+}
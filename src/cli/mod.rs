@@ -1,3 +1,7 @@
 //! Module for parsing command line arguments.
-//! Module defines `Args` struct for holding the parsed arguments.
+//! Module defines `Args` struct for holding the parsed arguments
+//! and reads defaults and presets from config files.
+//! Shell completions and the man page are generated from `Args`.
+pub mod config_file;
+pub mod generate;
 pub mod parser;
@@ -1,28 +1,497 @@
 //! Command line arguments parser. Holds the `Args` struct that is used to parse the command line arguments.
-use clap::Parser;
+use super::config_file::Preset;
+use crate::errors::MassMoveError;
+use crate::operations::conflict::{OnConflict, OnDuplicate};
+use crate::operations::file_filter::{MetadataFilter, PermFilter};
+use crate::output::{ColorChoice, OutputFormat, Verbosity};
+use crate::rename::SortOrder;
+use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-/// MassMove (mmv) is a command line tool that renames files and directories in a given directory.
+/// MassMove (mmv) is a command line tool that renames files by patterns.
+///
 /// This tool is useful when you want to rename multiple files and directories in a directory.
 /// Use the `--force` flag to overwrite existing files and directories.
+/// Several pairs of source and target paths may be given, as positional arguments or with `-e`,
+/// they are moved together as one batch.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 pub struct Args {
-    #[arg(short, long)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, long, overrides_with = "no_force")]
     force: bool,
-    source_path: String,
-    target_path: String,
+    /// Do not replace existing files, e.g. when `force` is set in a config file.
+    #[arg(long, overrides_with = "force")]
+    no_force: bool,
+    /// What to do with a target that exists or is a target of another pair [default: error].
+    #[arg(long, value_enum, value_name = "POLICY", conflicts_with = "force")]
+    on_conflict: Option<OnConflict>,
+    /// What to do with a target that exists and has the same size and hash as its source.
+    #[arg(long, value_enum, value_name = "POLICY")]
+    on_duplicate: Option<OnDuplicate>,
+    /// Format of suffixes added with `--on-conflict suffix`, `{n}` is the number [default: " ({n})"].
+    #[arg(long, value_name = "FORMAT", allow_hyphen_values = true)]
+    suffix_format: Option<String>,
+    /// Create missing parent directories of targets, e.g. for `#{artist}/#{album}/#{title}.mp3`.
+    #[arg(short = 'p', long, overrides_with = "no_create_dirs")]
+    create_dirs: bool,
+    /// Do not create missing parent directories of targets.
+    #[arg(long, overrides_with = "create_dirs")]
+    no_create_dirs: bool,
+    /// Print planned moves without moving files.
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+    /// Fail with a diagnostic instead of moving files whose names are not valid UTF-8.
+    #[arg(long, overrides_with = "no_strict_utf8")]
+    strict_utf8: bool,
+    /// Move files whose names are not valid UTF-8.
+    #[arg(long, overrides_with = "strict_utf8")]
+    no_strict_utf8: bool,
+    /// Number of threads used to scan the source directory and to move independent files (0 - number of CPUs) [default: 1].
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Skip files whose names match the glob pattern (may be repeated).
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Skip files ignored by `.gitignore`, `.ignore` and `.mmvignore` files.
+    #[arg(long, overrides_with = "no_ignore_files")]
+    ignore_files: bool,
+    /// Do not skip files ignored by ignore files.
+    #[arg(long, overrides_with = "ignore_files")]
+    no_ignore_files: bool,
+    /// Move only files of at least this size, e.g. `10M` (suffixes: K, M, G, T).
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+    /// Move only files of at most this size, e.g. `512K` (suffixes: K, M, G, T).
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Move only files modified more than this time ago, e.g. `7d` (suffixes: s, m, h, d, w).
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    older_than: Option<Duration>,
+    /// Move only files modified less than this time ago, e.g. `12h` (suffixes: s, m, h, d, w).
+    #[arg(long, value_name = "AGE", value_parser = parse_duration)]
+    newer_than: Option<Duration>,
+    /// Move only files with matching permissions: `644` exactly, `-644` all bits set, `/022` any bit set.
+    #[arg(long, value_name = "MODE", allow_hyphen_values = true)]
+    perm: Option<PermFilter>,
+    /// Move only empty files.
+    #[arg(long)]
+    empty: bool,
+    /// Add a pair of source and target paths (may be repeated).
+    #[arg(
+        short = 'e',
+        long = "expression",
+        num_args = 2,
+        value_names = ["SOURCE", "TARGET"]
+    )]
+    expressions: Vec<OsString>,
+    /// Read pairs of files to move from a file (`-` for stdin), one `from<TAB>to` per line.
+    #[arg(long, value_name = "PATH")]
+    from_file: Option<PathBuf>,
+    /// Pairs in `--from-file` are separated by NUL: `from\0to\0`.
+    #[arg(short = '0', long, requires = "from_file")]
+    null: bool,
+    /// Edit names of matched files in a text editor, positional arguments are SOURCE paths only.
+    #[arg(long, conflicts_with_all = ["expressions", "from_file"])]
+    edit: bool,
+    /// Editor command for `--edit` [default: $VISUAL, $EDITOR or vi].
+    #[arg(long, value_name = "COMMAND", requires = "edit")]
+    editor: Option<String>,
+    /// Delete files whose lines are emptied in `--edit` mode.
+    #[arg(long, requires = "edit")]
+    allow_delete: bool,
+    /// Write the resolved rename plan to a JSON file instead of moving files.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["edit", "dry_run"])]
+    plan_out: Option<PathBuf>,
+    /// Apply a plan written by `--plan-out`, it is refused if source files changed since then.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["paths", "expressions", "from_file", "edit", "plan_out"]
+    )]
+    apply: Option<PathBuf>,
+    /// Do not stop at the first error: report every failure and move what can be moved safely.
+    #[arg(short, long, overrides_with = "no_keep_going")]
+    keep_going: bool,
+    /// Stop at the first error.
+    #[arg(long, overrides_with = "keep_going")]
+    no_keep_going: bool,
+    /// Exit with success if a SOURCE pattern matches no files.
+    #[arg(long, overrides_with = "no_allow_no_match")]
+    allow_no_match: bool,
+    /// Fail if a SOURCE pattern matches no files.
+    #[arg(long, overrides_with = "allow_no_match")]
+    no_allow_no_match: bool,
+    /// Format of the output: records of planned, moved, skipped and failed pairs and a summary [default: text].
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    output: Option<OutputFormat>,
+    /// Print nothing but errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print every pair with its status and a summary line.
+    #[arg(short, long)]
+    verbose: bool,
+    /// Color names in the output, changed parts are highlighted and names are aligned [default: auto].
+    #[arg(long, value_enum, value_name = "WHEN")]
+    color: Option<ColorChoice>,
+    /// Do not show the progress line on stderr, it is shown for the text output on a terminal.
+    #[arg(long)]
+    no_progress: bool,
+    /// Verify files copied to another file system: compare BLAKE3 checksums of the source and the copy.
+    #[arg(long, overrides_with = "no_verify")]
+    verify: bool,
+    /// Do not verify files copied to another file system.
+    #[arg(long, overrides_with = "verify")]
+    no_verify: bool,
+    /// Match SOURCE patterns case-insensitively (ASCII letters).
+    #[arg(short, long, overrides_with = "no_ignore_case")]
+    ignore_case: bool,
+    /// Match SOURCE patterns case-sensitively.
+    #[arg(long, overrides_with = "ignore_case")]
+    no_ignore_case: bool,
+    /// Order of matched files of a SOURCE pattern [default: none].
+    #[arg(long, value_enum, value_name = "ORDER")]
+    sort: Option<SortOrder>,
+    /// Use a named preset of the config file: its patterns and settings.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["edit", "apply"])]
+    preset: Option<String>,
+    /// Read this config file instead of `~/.config/mmv/config.toml`.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Do not read config files.
+    #[arg(long, conflicts_with_all = ["config", "preset"])]
+    no_config: bool,
+    /// Pairs of source and target paths: SOURCE TARGET [SOURCE TARGET]...
+    #[arg(value_name = "SOURCE TARGET")]
+    paths: Vec<OsString>,
+}
+
+/// Commands that print files generated from the arguments definition instead of moving files.
+#[derive(Subcommand, Debug, Clone, Copy)]
+pub enum Command {
+    /// Print a completion script of the shell.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page in the roff format.
+    Man,
 }
 
 impl Args {
-    pub fn source_path(&self) -> &str {
-        &self.source_path
+    /// Function returns source paths of `--edit` mode.
+    pub fn edit_sources(&self) -> Result<Vec<&Path>, MassMoveError> {
+        if self.paths.is_empty() {
+            return Err(MassMoveError::InvalidArguments(
+                "no SOURCE given".to_owned(),
+            ));
+        }
+        Ok(self.paths.iter().map(Path::new).collect())
+    }
+
+    /// Function returns all pairs of source and target paths:
+    /// positional pairs first, then pairs given with `-e`.
+    pub fn pairs(&self) -> Result<Vec<(&Path, &OsStr)>, MassMoveError> {
+        if !self.paths.len().is_multiple_of(2) {
+            return Err(MassMoveError::InvalidArguments(
+                "expected pairs of SOURCE and TARGET".to_owned(),
+            ));
+        }
+
+        let pairs: Vec<(&Path, &OsStr)> = self
+            .paths
+            .chunks(2)
+            .chain(self.expressions.chunks(2))
+            .map(|pair| (Path::new(&pair[0]), pair[1].as_os_str()))
+            .collect();
+
+        if pairs.is_empty() && self.from_file.is_none() {
+            return Err(MassMoveError::InvalidArguments(
+                "no SOURCE and TARGET given".to_owned(),
+            ));
+        }
+        Ok(pairs)
+    }
+
+    /// Function adds patterns of a preset: with a preset target every positional argument
+    /// is a SOURCE path moved to the target, preset pairs are added as `-e` pairs.
+    pub fn with_preset(mut self, preset: &Preset) -> Result<Self, MassMoveError> {
+        if let Some(target) = &preset.target {
+            if self.paths.is_empty() {
+                return Err(MassMoveError::InvalidArguments(
+                    "no SOURCE given for the preset target".to_owned(),
+                ));
+            }
+            self.paths = self
+                .paths
+                .into_iter()
+                .flat_map(|source| [source, OsString::from(target)])
+                .collect();
+        }
+        for (source, target) in &preset.pairs {
+            self.expressions.push(OsString::from(source));
+            self.expressions.push(OsString::from(target));
+        }
+        Ok(self)
+    }
+
+    pub fn subcommand(&self) -> Option<Command> {
+        self.command
+    }
+
+    pub fn force(&self) -> Option<bool> {
+        flag(self.force, self.no_force)
+    }
+
+    pub fn on_conflict(&self) -> Option<OnConflict> {
+        self.on_conflict
+    }
+
+    pub fn on_duplicate(&self) -> Option<OnDuplicate> {
+        self.on_duplicate
+    }
+
+    pub fn suffix_format(&self) -> Option<&str> {
+        self.suffix_format.as_deref()
+    }
+
+    pub fn create_dirs(&self) -> Option<bool> {
+        flag(self.create_dirs, self.no_create_dirs)
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn pair_list_path(&self) -> Option<&Path> {
+        self.from_file.as_deref()
+    }
+
+    pub fn plan_out(&self) -> Option<&Path> {
+        self.plan_out.as_deref()
+    }
+
+    pub fn apply(&self) -> Option<&Path> {
+        self.apply.as_deref()
+    }
+
+    pub fn null(&self) -> bool {
+        self.null
+    }
+
+    pub fn edit(&self) -> bool {
+        self.edit
+    }
+
+    pub fn editor(&self) -> Option<&str> {
+        self.editor.as_deref()
+    }
+
+    pub fn allow_delete(&self) -> bool {
+        self.allow_delete
+    }
+
+    pub fn keep_going(&self) -> Option<bool> {
+        flag(self.keep_going, self.no_keep_going)
+    }
+
+    pub fn allow_no_match(&self) -> Option<bool> {
+        flag(self.allow_no_match, self.no_allow_no_match)
+    }
+
+    pub fn output(&self) -> Option<OutputFormat> {
+        self.output
+    }
+
+    /// Function returns the amount of the text output chosen with `-q` and `-v`.
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    pub fn color(&self) -> Option<ColorChoice> {
+        self.color
+    }
+
+    pub fn no_progress(&self) -> bool {
+        self.no_progress
+    }
+
+    pub fn verify(&self) -> Option<bool> {
+        flag(self.verify, self.no_verify)
+    }
+
+    pub fn ignore_case(&self) -> Option<bool> {
+        flag(self.ignore_case, self.no_ignore_case)
+    }
+
+    pub fn sort(&self) -> Option<SortOrder> {
+        self.sort
+    }
+
+    pub fn preset(&self) -> Option<&str> {
+        self.preset.as_deref()
+    }
+
+    pub fn config(&self) -> Option<&Path> {
+        self.config.as_deref()
+    }
+
+    pub fn no_config(&self) -> bool {
+        self.no_config
+    }
+
+    pub fn strict_utf8(&self) -> Option<bool> {
+        flag(self.strict_utf8, self.no_strict_utf8)
+    }
+
+    pub fn jobs(&self) -> Option<usize> {
+        self.jobs
+    }
+
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
     }
 
-    pub fn target_path(&self) -> &str {
-        &self.target_path
+    pub fn ignore_files(&self) -> Option<bool> {
+        flag(self.ignore_files, self.no_ignore_files)
     }
 
-    pub fn force(&self) -> bool {
-        self.force
+    /// Function collects metadata predicates of matched files.
+    pub fn metadata_filter(&self) -> MetadataFilter {
+        MetadataFilter {
+            min_size: self.min_size,
+            max_size: self.max_size,
+            older_than: self.older_than,
+            newer_than: self.newer_than,
+            perm: self.perm,
+            empty: self.empty,
+        }
+    }
+}
+
+/// Function returns the value of a flag with a `--no-` counterpart: `None` if neither is given,
+/// so the value of config files is used. The last of them wins.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Function splits a value into a number and a suffix, e.g. `10M` -> (10, "M").
+fn split_number(value: &str) -> Result<(u64, &str), String> {
+    let position = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, suffix) = value.split_at(position);
+    match number.parse::<u64>() {
+        Ok(number) => Ok((number, suffix)),
+        Err(_) => Err(format!("invalid number: {}", value)),
+    }
+}
+
+/// Function parses a size with an optional binary suffix: `100`, `10K`, `10M`, `1G`, `1T`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, suffix) = split_number(value)?;
+    let multiplier: u64 = match suffix.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size suffix: {}", value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size is too big: {}", value))
+}
+
+/// Function parses a duration with a unit suffix: `30s`, `15m`, `12h`, `7d`, `2w`.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, suffix) = split_number(value)?;
+    let seconds: u64 = match suffix {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid duration suffix, expected s, m, h, d or w: {}",
+                value
+            ))
+        }
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration is too big: {}", value))
+}
+
+#[test]
+fn test_parse_size() {
+    let cases: Vec<(&str, Option<u64>)> = vec![
+        ("100", Some(100)),
+        ("10K", Some(10 * 1024)),
+        ("10M", Some(10 * 1024 * 1024)),
+        ("1g", Some(1024 * 1024 * 1024)),
+        ("2KB", Some(2048)),
+        ("10X", None),
+        ("M", None),
+    ];
+
+    for case in cases {
+        assert_eq!(parse_size(case.0).ok(), case.1, "size: {}", case.0);
+    }
+}
+
+#[test]
+fn test_parse_duration() {
+    let cases: Vec<(&str, Option<u64>)> = vec![
+        ("30s", Some(30)),
+        ("15m", Some(15 * 60)),
+        ("12h", Some(12 * 60 * 60)),
+        ("7d", Some(7 * 24 * 60 * 60)),
+        ("2w", Some(14 * 24 * 60 * 60)),
+        ("7", None),
+        ("d", None),
+    ];
+
+    for case in cases {
+        assert_eq!(
+            parse_duration(case.0).ok().map(|d| d.as_secs()),
+            case.1,
+            "duration: {}",
+            case.0
+        );
+    }
+}
+
+#[test]
+fn test_no_flags() {
+    use clap::Parser;
+
+    let cases: Vec<(Vec<&str>, Option<bool>)> = vec![
+        (vec![], None),
+        (vec!["--force"], Some(true)),
+        (vec!["--no-force"], Some(false)),
+        (vec!["--force", "--no-force"], Some(false)),
+        (vec!["--no-force", "-f"], Some(true)),
+    ];
+
+    for case in cases {
+        let args = Args::try_parse_from(["mmv"].iter().chain(&case.0)).unwrap();
+        assert_eq!(args.force(), case.1, "args: {:?}", case.0);
     }
 }
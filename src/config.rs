@@ -1,37 +1,385 @@
 //! Module that holds the Config struct that holds the configuration setting for the mmv.
+use crate::cli::config_file::Settings;
 use crate::cli::parser::Args;
+use crate::operations::conflict::{OnConflict, OnDuplicate, DEFAULT_SUFFIX_FORMAT};
+use crate::operations::file_filter::MetadataFilter;
+use crate::operations::file_system::{FileSystem, RealFileSystem};
+use crate::output::{ColorChoice, OutputFormat, Verbosity};
+use crate::rename::SortOrder;
+use std::env;
+use std::sync::Arc;
 
 /// Config is strcut that holds the configuration setting for the mmv.
 /// There are only ways to create a Config struct:
-/// 1. `Config::new()` - creates a new Config struct with default settings.
-/// 2. `Config::from_args(args: &Args) - create a new Config from the command line arguments.`
+/// 1. `Config::new()` - creates a new Config struct with default settings,
+///    it is changed with `with_*` builder methods.
+/// 2. `Config::from_args(args: &Args, settings: &Settings)` - create a new Config from the command line arguments,
+///    options that are not given are taken from settings of config files.
 ///
 /// Usage:
 /// ```rust
-/// use crate::config::Config;
-/// use crate::cli::parser::Args;
+/// use mmv::Config;
 ///
-/// let args = Args::parse();
-/// let config = Config::from_args(&args);
+/// let config = Config::new()
+///     .with_force_move(true)
+///     .with_exclude("*.tmp")
+///     .with_jobs(4);
+/// assert!(config.force_move());
+/// assert_eq!(config.excludes(), ["*.tmp"]);
+/// assert!(config.is_parallel());
 /// ```
+#[derive(Clone)]
 pub struct Config {
     force_move: bool,
+    on_conflict: OnConflict,
+    on_duplicate: Option<OnDuplicate>,
+    suffix_format: String,
+    create_dirs: bool,
+    dry_run: bool,
+    strict_utf8: bool,
+    jobs: usize,
+    excludes: Vec<String>,
+    ignore_files: bool,
+    metadata_filter: MetadataFilter,
+    editor: String,
+    allow_delete: bool,
+    keep_going: bool,
+    allow_no_match: bool,
+    output_format: OutputFormat,
+    verbosity: Verbosity,
+    color: ColorChoice,
+    progress: bool,
+    verify: bool,
+    ignore_case: bool,
+    sort_order: SortOrder,
+    file_system: Arc<dyn FileSystem>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
 }
 
 impl Config {
     /// Construct a new Config struct with default settings.
     pub fn new() -> Self {
-        Config { force_move: false }
+        Config {
+            force_move: false,
+            on_conflict: OnConflict::Error,
+            on_duplicate: None,
+            suffix_format: String::from(DEFAULT_SUFFIX_FORMAT),
+            create_dirs: false,
+            dry_run: false,
+            strict_utf8: false,
+            jobs: 1,
+            excludes: Vec::new(),
+            ignore_files: false,
+            metadata_filter: MetadataFilter::default(),
+            editor: String::from("vi"),
+            allow_delete: false,
+            keep_going: false,
+            allow_no_match: false,
+            output_format: OutputFormat::Text,
+            verbosity: Verbosity::Normal,
+            color: ColorChoice::Auto,
+            progress: false,
+            verify: false,
+            ignore_case: false,
+            sort_order: SortOrder::None,
+            file_system: Arc::new(RealFileSystem),
+        }
     }
 
     /// Construct a new Config struct from the command line arguments.
-    pub fn from_args(args: &Args) -> Self {
+    /// Options that are not given on the command line are taken from the settings of config files.
+    #[doc(hidden)]
+    pub fn from_args(args: &Args, settings: &Settings) -> Self {
+        let is_set = |flag: Option<bool>, setting: Option<bool>| flag.or(setting).unwrap_or(false);
+        let mut excludes = settings.exclude.clone();
+        excludes.extend_from_slice(args.exclude());
         Config {
-            force_move: args.force(),
+            force_move: is_set(args.force(), settings.force),
+            on_conflict: args
+                .on_conflict()
+                .or(settings.on_conflict)
+                .unwrap_or_default(),
+            on_duplicate: args.on_duplicate().or(settings.on_duplicate),
+            suffix_format: args
+                .suffix_format()
+                .or(settings.suffix_format.as_deref())
+                .unwrap_or(DEFAULT_SUFFIX_FORMAT)
+                .to_owned(),
+            create_dirs: is_set(args.create_dirs(), settings.create_dirs),
+            dry_run: args.dry_run() || args.plan_out().is_some(),
+            strict_utf8: is_set(args.strict_utf8(), settings.strict_utf8),
+            jobs: args.jobs().or(settings.jobs).unwrap_or(1),
+            excludes,
+            ignore_files: is_set(args.ignore_files(), settings.ignore_files),
+            metadata_filter: args.metadata_filter(),
+            editor: Self::editor_from_args(args, settings),
+            allow_delete: args.allow_delete(),
+            keep_going: is_set(args.keep_going(), settings.keep_going),
+            allow_no_match: is_set(args.allow_no_match(), settings.allow_no_match),
+            output_format: args.output().or(settings.output).unwrap_or_default(),
+            verbosity: args.verbosity(),
+            color: args.color().or(settings.color).unwrap_or_default(),
+            progress: !args.no_progress() && settings.progress.unwrap_or(true),
+            verify: is_set(args.verify(), settings.verify),
+            ignore_case: is_set(args.ignore_case(), settings.ignore_case),
+            sort_order: args.sort().or(settings.sort).unwrap_or_default(),
+            file_system: Arc::new(RealFileSystem),
+        }
+    }
+
+    /// Function chooses the editor for `--edit`: the `--editor` argument,
+    /// the `editor` setting, `$VISUAL`, `$EDITOR` or `vi`.
+    fn editor_from_args(args: &Args, settings: &Settings) -> String {
+        if let Some(editor) = args.editor().or(settings.editor.as_deref()) {
+            return editor.to_owned();
         }
+        ["VISUAL", "EDITOR"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|editor| !editor.trim().is_empty())
+            .unwrap_or_else(|| String::from("vi"))
+    }
+
+    /// Function allows to replace existing files.
+    pub fn with_force_move(mut self, force_move: bool) -> Self {
+        self.force_move = force_move;
+        self
+    }
+
+    /// Function sets the policy for targets that exist or collide.
+    pub fn with_on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Function sets the policy for existing targets with the same content as their sources.
+    pub fn with_on_duplicate(mut self, on_duplicate: OnDuplicate) -> Self {
+        self.on_duplicate = Some(on_duplicate);
+        self
+    }
+
+    /// Function sets the format of suffixes of `OnConflict::Suffix`, `{n}` is the number.
+    pub fn with_suffix_format(mut self, suffix_format: impl Into<String>) -> Self {
+        self.suffix_format = suffix_format.into();
+        self
+    }
+
+    /// Function allows creating missing parent directories of targets.
+    pub fn with_create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Function enables dry-run mode: moves are planned and validated, but not done.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Function rejects paths that are not valid UTF-8.
+    pub fn with_strict_utf8(mut self, strict_utf8: bool) -> Self {
+        self.strict_utf8 = strict_utf8;
+        self
+    }
+
+    /// Function sets the number of threads, 0 means the number of CPUs.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Function adds a glob pattern of file names that are never moved.
+    pub fn with_exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.excludes.push(pattern.into());
+        self
+    }
+
+    /// Function enables `.gitignore`, `.ignore` and `.mmvignore` files.
+    pub fn with_ignore_files(mut self, ignore_files: bool) -> Self {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    /// Function sets predicates on size, age and permissions of matched files.
+    pub fn with_metadata_filter(mut self, metadata_filter: MetadataFilter) -> Self {
+        self.metadata_filter = metadata_filter;
+        self
+    }
+
+    /// Function enables keep-going mode: all errors are collected and other files are moved.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Function allows source patterns that match no files.
+    pub fn with_allow_no_match(mut self, allow_no_match: bool) -> Self {
+        self.allow_no_match = allow_no_match;
+        self
+    }
+
+    /// Function sets the file system files are matched and moved on (e.g. `MemoryFileSystem`).
+    pub fn with_file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = file_system;
+        self
+    }
+
+    /// Function sets the amount of the text output.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Function sets when the text output is colored.
+    pub fn with_color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Function allows the progress line on stderr.
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Function makes copy-based moves compare checksums of the source and the copy.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Function makes SOURCE patterns case-insensitive.
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    /// Function sets the order of matched files of a SOURCE pattern.
+    pub fn with_sort_order(mut self, sort_order: SortOrder) -> Self {
+        self.sort_order = sort_order;
+        self
     }
 
+    /// Existing targets are replaced: `--force` or `--on-conflict=overwrite`.
     pub fn force_move(&self) -> bool {
-        self.force_move
+        self.force_move || self.on_conflict == OnConflict::Overwrite
+    }
+
+    /// Policy for targets that exist or collide.
+    pub fn on_conflict(&self) -> OnConflict {
+        self.on_conflict
+    }
+
+    /// Policy for existing targets with the same content as their sources,
+    /// `None` if duplicates are conflicts.
+    pub fn on_duplicate(&self) -> Option<OnDuplicate> {
+        self.on_duplicate
+    }
+
+    /// Format of suffixes of `OnConflict::Suffix`, e.g. ` ({n})` or `-{n}`.
+    pub fn suffix_format(&self) -> &str {
+        &self.suffix_format
+    }
+
+    /// Missing parent directories of targets are created before files are moved.
+    pub fn create_dirs(&self) -> bool {
+        self.create_dirs
+    }
+
+    /// In dry-run mode moves are planned and validated, but files are not moved.
+    /// `--plan-out` implies dry-run.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn strict_utf8(&self) -> bool {
+        self.strict_utf8
+    }
+
+    /// Number of threads for scanning and moving, 0 means the number of CPUs.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Glob patterns of file names that are never moved.
+    pub fn excludes(&self) -> &[String] {
+        &self.excludes
+    }
+
+    pub fn ignore_files(&self) -> bool {
+        self.ignore_files
+    }
+
+    /// Predicates on size, age and permissions of matched files.
+    pub fn metadata_filter(&self) -> &MetadataFilter {
+        &self.metadata_filter
+    }
+
+    pub fn editor(&self) -> &str {
+        &self.editor
+    }
+
+    pub fn allow_delete(&self) -> bool {
+        self.allow_delete
+    }
+
+    /// In keep-going mode all errors are collected and files without errors are moved.
+    pub fn keep_going(&self) -> bool {
+        self.keep_going
+    }
+
+    /// A SOURCE pattern that matches no files is not an error.
+    pub fn allow_no_match(&self) -> bool {
+        self.allow_no_match
+    }
+
+    /// Format of the output: human readable text, JSON, NDJSON or TSV.
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// Amount of the text output: quiet, normal or verbose.
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// When the text output is colored.
+    pub fn color(&self) -> ColorChoice {
+        self.color
+    }
+
+    /// The progress line is allowed, it is shown only for the text output on a terminal.
+    pub fn progress(&self) -> bool {
+        self.progress
+    }
+
+    /// Files copied to another file system are verified with BLAKE3 checksums.
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+
+    /// SOURCE patterns are matched case-insensitively (ASCII letters).
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Order of matched files of a SOURCE pattern.
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    /// File system files are matched and moved on, the real one by default.
+    pub fn file_system(&self) -> &Arc<dyn FileSystem> {
+        &self.file_system
+    }
+
+    /// Function returns true if scanning and moving should be done in parallel.
+    pub fn is_parallel(&self) -> bool {
+        self.jobs != 1
     }
 }
@@ -0,0 +1,67 @@
+//! Helpers to work with raw bytes of OS strings (file names and paths).
+//! On Unix file names are arbitrary byte sequences, so matching and template substitution
+//! are done on bytes to preserve names that are not valid UTF-8.
+use crate::errors::MassMoveError;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+/// Function returns raw bytes of an OS string.
+pub fn os_str_bytes(s: &OsStr) -> &[u8] {
+    s.as_encoded_bytes()
+}
+
+/// Function builds an OS string back from raw bytes.
+/// On Unix every byte sequence is a valid file name, on other platforms bytes must be valid UTF-8.
+#[cfg(unix)]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> Result<OsString, MassMoveError> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes))
+}
+
+/// Function builds an OS string back from raw bytes.
+/// On Unix every byte sequence is a valid file name, on other platforms bytes must be valid UTF-8.
+#[cfg(not(unix))]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> Result<OsString, MassMoveError> {
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(OsString::from(s)),
+        Err(err) => Err(MassMoveError::InvalidEncoding(escape_bytes(err.as_bytes()))),
+    }
+}
+
+/// Function escapes bytes for diagnostics: valid UTF-8 is kept as is,
+/// invalid bytes are printed as `\xNN`.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Function checks that a path is valid UTF-8.
+/// It is used in strict mode to report invalid names instead of moving them.
+pub fn check_utf8(path: &Path) -> Result<(), MassMoveError> {
+    match path.to_str() {
+        Some(_) => Ok(()),
+        None => Err(MassMoveError::InvalidEncoding(escape_bytes(os_str_bytes(
+            path.as_os_str(),
+        )))),
+    }
+}
+
+#[test]
+fn test_escape_bytes() {
+    let cases: Vec<(&[u8], &str)> = vec![
+        (b"file.txt", "file.txt"),
+        (b"file-\xE9.txt", "file-\\xE9.txt"),
+        ("файл.txt".as_bytes(), "файл.txt"),
+        (b"\xFF\xFE", "\\xFF\\xFE"),
+    ];
+
+    for case in cases {
+        assert_eq!(escape_bytes(case.0), case.1, "failed for: {:?}", case.0);
+    }
+}
@@ -1,36 +1,287 @@
-/// Module defines the base error type for the application - MassMoveError.
-/// MassMoveError is an enum that represents all possible errors that can occur in the application.
-/// It is used in all modules to return errors.
-///
-/// Usage (code example from file_matcher.rs):
-/// ```rust
-/// use crate::errors::MassMoveError;
-/// use std::fs;
-///
-/// fn read_source_directory(&self) -> Result<ReadDir, MassMoveError> {
-///    match fs::read_dir(&self.source_directory) {
-///        Ok(read_directory) => Ok(read_directory),
-///        Err(_) => Err(MassMoveError::DirectoryNotFound(
-///            self.source_directory.clone(),
-///        )),
-///    }
-/// }
-/// ```
+//! Module defines the base error type for the application - MassMoveError.
+//! MassMoveError is an enum that represents all possible errors that can occur in the application.
+//! It is used in all modules to return errors.
+//!
+//! Errors of file system operations keep the operation, the paths and the original `io::Error`,
+//! it is returned by `Error::source`. `display_chain` prints an error with all its causes.
+//!
+//! Usage (code example from file_matcher.rs):
+//! ```rust
+//! use mmv::errors::{MassMoveError, Operation};
+//! use std::fs::{self, ReadDir};
+//! use std::path::Path;
+//!
+//! fn read_source_directory(directory: &Path) -> Result<ReadDir, MassMoveError> {
+//!     fs::read_dir(directory).map_err(|source| MassMoveError::Io {
+//!         operation: Operation::ReadDirectory,
+//!         path: directory.to_owned(),
+//!         source,
+//!     })
+//! }
+//!
+//! let err = read_source_directory(Path::new("/no/such/directory")).unwrap_err();
+//! assert_eq!(
+//!     err.display_chain(),
+//!     "mmv: Failed to read directory /no/such/directory\n  caused by: No such file or directory (os error 2)"
+//! );
+//! ```
+use std::error::Error;
+use std::path::PathBuf;
 use std::{fmt::Display, io};
 
+/// Exit codes of the process, they follow `sysexits.h` where it is possible.
+/// No files matched for a SOURCE pattern.
+pub const EX_NO_MATCH: i32 = 1;
+/// Some errors are found in `--keep-going` mode, other files are moved.
+pub const EX_INCOMPLETE: i32 = 2;
+/// Invalid command line arguments or patterns.
+pub const EX_USAGE: i32 = 64;
+/// Invalid input data: pair list, edited list, plan or encoding of a path.
+pub const EX_DATAERR: i32 = 65;
+/// Source directory does not exist.
+pub const EX_NOINPUT: i32 = 66;
+/// The editor could not be run.
+pub const EX_UNAVAILABLE: i32 = 69;
+/// The batch has conflicts: existing targets, collisions, duplicate sources or cycles.
+pub const EX_CANTCREAT: i32 = 73;
+/// A file could not be moved or rolled back.
+pub const EX_IOERR: i32 = 74;
+/// Files changed since the plan was made, the plan should be made again.
+pub const EX_TEMPFAIL: i32 = 75;
+/// Permission denied.
+pub const EX_NOPERM: i32 = 77;
+/// Invalid configuration file.
+pub const EX_CONFIG: i32 = 78;
+
+/// File system operation that failed, it is a part of `MassMoveError::Io`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Operation {
+    ReadDirectory,
+    ReadFile,
+    WriteFile,
+    ReadMetadata,
+    DeleteFile,
+    LinkFile,
+    CreateDirectory,
+    CreateTempDirectory,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operation = match self {
+            Operation::ReadDirectory => "read directory",
+            Operation::ReadFile => "read file",
+            Operation::WriteFile => "write file",
+            Operation::ReadMetadata => "read metadata of",
+            Operation::DeleteFile => "delete file",
+            Operation::LinkFile => "link file",
+            Operation::CreateDirectory => "create directory",
+            Operation::CreateTempDirectory => "create temporary directory in",
+        };
+        write!(f, "{}", operation)
+    }
+}
+
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum MassMoveError {
     InvalidSourcePath(String),
     InvalidTargetPath(String),
+    InvalidEncoding(String),
+    InvalidArguments(String),
+    InvalidPairList(String),
+    InvalidEditedList(String),
+    EditorError(String),
+    InvalidPlan(String),
+    PlanDrift(String),
+    InvalidConfig(String),
 
     DirectoryNotFound(String),
     PermissionDenied(io::Error),
 
     NoFilesForPattern(String),
+    InvalidExcludePattern(String),
 
     FileAlreadyExists(String),
-    MoveError(String),
+    TargetCollision(String),
+    DuplicateSource(String),
+    MoveCycle(String),
+    MoveError {
+        from: PathBuf,
+        to: PathBuf,
+        source: io::Error,
+    },
+    /// Failed move back of a done move, `from` is the target of the move.
+    RollbackError {
+        from: PathBuf,
+        to: PathBuf,
+        source: io::Error,
+    },
+    RolledBack(Box<MassMoveError>, usize),
+    Incomplete(Box<MassMoveError>, usize),
+    Io {
+        operation: Operation,
+        path: PathBuf,
+        source: io::Error,
+    },
     Error(io::Error),
+
+    Multiple(Vec<MassMoveError>),
+}
+
+impl MassMoveError {
+    /// Function returns a stable machine-readable name of the error, it is used in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MassMoveError::InvalidSourcePath(_) => "invalid_source_path",
+            MassMoveError::InvalidTargetPath(_) => "invalid_target_path",
+            MassMoveError::InvalidEncoding(_) => "invalid_encoding",
+            MassMoveError::InvalidArguments(_) => "invalid_arguments",
+            MassMoveError::InvalidPairList(_) => "invalid_pair_list",
+            MassMoveError::InvalidEditedList(_) => "invalid_edited_list",
+            MassMoveError::EditorError(_) => "editor_error",
+            MassMoveError::InvalidPlan(_) => "invalid_plan",
+            MassMoveError::PlanDrift(_) => "plan_drift",
+            MassMoveError::InvalidConfig(_) => "invalid_config",
+            MassMoveError::DirectoryNotFound(_) => "directory_not_found",
+            MassMoveError::PermissionDenied(_) => "permission_denied",
+            MassMoveError::NoFilesForPattern(_) => "no_files_for_pattern",
+            MassMoveError::InvalidExcludePattern(_) => "invalid_exclude_pattern",
+            MassMoveError::FileAlreadyExists(_) => "file_already_exists",
+            MassMoveError::TargetCollision(_) => "target_collision",
+            MassMoveError::DuplicateSource(_) => "duplicate_source",
+            MassMoveError::MoveCycle(_) => "move_cycle",
+            MassMoveError::MoveError { .. } => "move_error",
+            MassMoveError::RollbackError { .. } => "rollback_error",
+            MassMoveError::RolledBack(_, _) => "rolled_back",
+            MassMoveError::Incomplete(_, _) => "incomplete",
+            MassMoveError::Io { .. } | MassMoveError::Error(_) => "io_error",
+            MassMoveError::Multiple(_) => "multiple",
+        }
+    }
+
+    /// Function returns the exit code of the process for the error (see `EX_*` constants).
+    /// A rolled back batch exits with the code of the error that caused the roll back,
+    /// several errors exit with the code of the first one.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MassMoveError::NoFilesForPattern(_) => EX_NO_MATCH,
+            MassMoveError::Incomplete(_, _) => EX_INCOMPLETE,
+            MassMoveError::InvalidSourcePath(_)
+            | MassMoveError::InvalidTargetPath(_)
+            | MassMoveError::InvalidArguments(_)
+            | MassMoveError::InvalidExcludePattern(_) => EX_USAGE,
+            MassMoveError::InvalidEncoding(_)
+            | MassMoveError::InvalidPairList(_)
+            | MassMoveError::InvalidEditedList(_)
+            | MassMoveError::InvalidPlan(_) => EX_DATAERR,
+            MassMoveError::DirectoryNotFound(_) => EX_NOINPUT,
+            MassMoveError::EditorError(_) => EX_UNAVAILABLE,
+            MassMoveError::FileAlreadyExists(_)
+            | MassMoveError::TargetCollision(_)
+            | MassMoveError::DuplicateSource(_)
+            | MassMoveError::MoveCycle(_) => EX_CANTCREAT,
+            MassMoveError::MoveError { source, .. }
+            | MassMoveError::RollbackError { source, .. }
+            | MassMoveError::Io { source, .. }
+            | MassMoveError::Error(source) => match source.kind() {
+                io::ErrorKind::PermissionDenied => EX_NOPERM,
+                _ => EX_IOERR,
+            },
+            MassMoveError::PlanDrift(_) => EX_TEMPFAIL,
+            MassMoveError::InvalidConfig(_) => EX_CONFIG,
+            MassMoveError::PermissionDenied(_) => EX_NOPERM,
+            MassMoveError::RolledBack(err, _) => err.exit_code(),
+            MassMoveError::Multiple(errors) => {
+                errors.first().map_or(EX_IOERR, MassMoveError::exit_code)
+            }
+        }
+    }
+
+    /// Function returns the error message with causes of the error, one cause per line.
+    /// Errors aggregated in `Multiple`, `RolledBack` and `Incomplete` are printed with their causes too.
+    pub fn display_chain(&self) -> String {
+        match self {
+            MassMoveError::Multiple(errors) => {
+                let messages: Vec<String> = errors.iter().map(|err| err.display_chain()).collect();
+                messages.join("\n")
+            }
+            MassMoveError::RolledBack(err, count) => {
+                format!(
+                    "{}\n{}",
+                    err.display_chain(),
+                    Self::rolled_back_message(*count)
+                )
+            }
+            MassMoveError::Incomplete(err, moved) => {
+                format!(
+                    "{}\n{}",
+                    err.display_chain(),
+                    Self::incomplete_message(err, *moved)
+                )
+            }
+            _ => {
+                let mut message = self.to_string();
+                let mut source = self.source();
+                while let Some(err) = source {
+                    message.push_str(&format!("\n  caused by: {}", err));
+                    source = err.source();
+                }
+                message
+            }
+        }
+    }
+
+    fn rolled_back_message(count: usize) -> String {
+        format!("mmv: Rolled back {} moved files", count)
+    }
+
+    fn incomplete_message(err: &MassMoveError, moved: usize) -> String {
+        let failed = match err {
+            MassMoveError::Multiple(errors) => errors.len(),
+            _ => 1,
+        };
+        format!(
+            "mmv: Kept going after {} errors, {} files moved",
+            failed, moved
+        )
+    }
+
+    /// Function collects results of independent operations (e.g. done in parallel).
+    /// It returns all values if every operation succeeded, otherwise all errors are aggregated
+    /// in `MassMoveError::Multiple` in the order of the results.
+    pub fn collect<T>(
+        results: impl IntoIterator<Item = Result<T, MassMoveError>>,
+    ) -> Result<Vec<T>, MassMoveError> {
+        let mut values = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(err) => errors.push(err),
+            }
+        }
+        MassMoveError::from_errors(errors).map(|_| values)
+    }
+
+    /// Function turns a list of errors into a single error: the error itself if it is the only one
+    /// or `MassMoveError::Multiple` otherwise.
+    /// Nested `MassMoveError::Multiple` errors are flattened.
+    pub fn from_errors(errors: Vec<MassMoveError>) -> Result<(), MassMoveError> {
+        let mut errors: Vec<MassMoveError> = errors
+            .into_iter()
+            .flat_map(|err| match err {
+                MassMoveError::Multiple(errors) => errors,
+                err => vec![err],
+            })
+            .collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(MassMoveError::Multiple(errors)),
+        }
+    }
 }
 
 /// Implementing From trait for io::Error to convert io::Error to MassMoveError.
@@ -52,24 +303,161 @@ impl Display for MassMoveError {
             MassMoveError::InvalidTargetPath(path) => {
                 write!(f, "mmv: Invalid target path: {}", path)
             }
+            MassMoveError::InvalidArguments(msg) => {
+                write!(f, "mmv: Invalid arguments: {}", msg)
+            }
+            MassMoveError::InvalidPairList(msg) => {
+                write!(f, "mmv: Invalid pair list: {}", msg)
+            }
+            MassMoveError::InvalidEditedList(msg) => {
+                write!(f, "mmv: Invalid edited list: {}", msg)
+            }
+            MassMoveError::EditorError(msg) => {
+                write!(f, "mmv: Editor failed: {}", msg)
+            }
+            MassMoveError::InvalidPlan(msg) => {
+                write!(f, "mmv: Invalid plan: {}", msg)
+            }
+            MassMoveError::PlanDrift(msg) => {
+                write!(f, "mmv: File system changed since the plan: {}", msg)
+            }
+            MassMoveError::InvalidConfig(msg) => {
+                write!(f, "mmv: Invalid config file: {}", msg)
+            }
+            MassMoveError::InvalidEncoding(path) => {
+                write!(f, "mmv: Invalid UTF-8 in path: {}", path)
+            }
             MassMoveError::DirectoryNotFound(path) => {
                 write!(f, "mmv: Directory `{}` no found", path)
             }
-            MassMoveError::PermissionDenied(err) => {
-                write!(f, "mmv: Permission denied: {}", err)
+            MassMoveError::PermissionDenied(_) => {
+                write!(f, "mmv: Permission denied")
             }
             MassMoveError::FileAlreadyExists(path) => {
                 write!(f, "mmv: Not able to replace existing file: {}", path)
             }
+            MassMoveError::TargetCollision(paths) => {
+                write!(
+                    f,
+                    "mmv: Several files are moved to the same target: {}",
+                    paths
+                )
+            }
+            MassMoveError::DuplicateSource(path) => {
+                write!(f, "mmv: File is matched by several sources: {}", path)
+            }
+            MassMoveError::MoveCycle(paths) => {
+                write!(f, "mmv: Files are moved in a cycle: {}", paths)
+            }
             MassMoveError::NoFilesForPattern(pattern) => {
                 write!(f, "mmv: Files for pattern '{}' not found", pattern)
             }
-            MassMoveError::MoveError(path) => {
-                write!(f, "mmv: Failed move: {}", path)
+            MassMoveError::InvalidExcludePattern(pattern) => {
+                write!(f, "mmv: Invalid exclude pattern: {}", pattern)
+            }
+            MassMoveError::MoveError { from, to, .. } => {
+                write!(
+                    f,
+                    "mmv: Failed move: {} -> {}",
+                    from.display(),
+                    to.display()
+                )
+            }
+            MassMoveError::RollbackError { from, to, .. } => {
+                write!(
+                    f,
+                    "mmv: Failed roll back: {} -> {}",
+                    from.display(),
+                    to.display()
+                )
+            }
+            MassMoveError::RolledBack(err, count) => {
+                write!(f, "{}\n{}", err, Self::rolled_back_message(*count))
             }
-            MassMoveError::Error(err) => {
-                write!(f, "mmv: {}", err)
+            MassMoveError::Incomplete(err, moved) => {
+                write!(f, "{}\n{}", err, Self::incomplete_message(err, *moved))
+            }
+            MassMoveError::Io {
+                operation, path, ..
+            } => {
+                write!(f, "mmv: Failed to {} {}", operation, path.display())
+            }
+            MassMoveError::Error(_) => {
+                write!(f, "mmv: I/O error")
+            }
+            MassMoveError::Multiple(errors) => {
+                let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+                write!(f, "{}", messages.join("\n"))
             }
         }
     }
 }
+
+impl Error for MassMoveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MassMoveError::MoveError { source, .. }
+            | MassMoveError::RollbackError { source, .. }
+            | MassMoveError::Io { source, .. }
+            | MassMoveError::PermissionDenied(source)
+            | MassMoveError::Error(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Function builds a failed move, it is used in tests.
+#[cfg(test)]
+fn move_error(kind: io::ErrorKind) -> MassMoveError {
+    MassMoveError::MoveError {
+        from: PathBuf::from("a"),
+        to: PathBuf::from("b"),
+        source: io::Error::from(kind),
+    }
+}
+
+#[test]
+fn test_exit_code() {
+    let cases: Vec<(MassMoveError, i32)> = vec![
+        (MassMoveError::NoFilesForPattern("*.txt".to_owned()), 1),
+        (MassMoveError::InvalidArguments(String::new()), 64),
+        (MassMoveError::FileAlreadyExists("a".to_owned()), 73),
+        (
+            MassMoveError::RolledBack(Box::new(move_error(io::ErrorKind::NotFound)), 1),
+            74,
+        ),
+        (
+            MassMoveError::Multiple(vec![
+                MassMoveError::TargetCollision("x <- a, b".to_owned()),
+                move_error(io::ErrorKind::NotFound),
+            ]),
+            73,
+        ),
+        (move_error(io::ErrorKind::PermissionDenied), 77),
+        (
+            MassMoveError::Incomplete(Box::new(move_error(io::ErrorKind::NotFound)), 0),
+            2,
+        ),
+    ];
+
+    for case in cases {
+        assert_eq!(case.0.exit_code(), case.1, "error: {}", case.0);
+    }
+}
+
+#[test]
+fn test_display_chain() {
+    let err = MassMoveError::RolledBack(
+        Box::new(MassMoveError::Multiple(vec![
+            move_error(io::ErrorKind::NotFound),
+            MassMoveError::FileAlreadyExists("c".to_owned()),
+        ])),
+        1,
+    );
+    assert_eq!(
+        err.display_chain(),
+        "mmv: Failed move: a -> b\n  caused by: entity not found\n\
+         mmv: Not able to replace existing file: c\n\
+         mmv: Rolled back 1 moved files"
+    );
+}
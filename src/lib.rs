@@ -0,0 +1,49 @@
+//! `mmv` moves (renames) files in bulk by patterns.
+//!
+//! A source pattern is a path with `*` in the file name, a target pattern uses `#1`, `#2`, ...
+//! for the parts of the file name matched by `*`. All pairs of files are planned and
+//! validated first and then moved as one batch: on a failure the done moves are rolled back.
+//!
+//! The library API is a builder: [`Rename`] (patterns) -> [`RenamePlan`] (resolved pairs)
+//! -> [`RenamePlan::validate`] -> [`RenamePlan::execute`]:
+//! ```rust
+//! use mmv::Rename;
+//! # use tempdir::TempDir;
+//!
+//! # fn main() -> Result<(), mmv::MassMoveError> {
+//! # let directory = TempDir::new("mmv-doc")?;
+//! # let dir = directory.path().display();
+//! # std::fs::File::create(format!("{dir}/notes-1.txt"))?;
+//! let plan = Rename::new()
+//!     .pattern(format!("{dir}/notes-*.txt"), format!("{dir}/notes-#1.md"))
+//!     .plan()?;
+//! plan.validate()?;
+//! plan.execute()?;
+//! # assert!(directory.path().join("notes-1.md").exists());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Lower level building blocks are available in [`operations`] (matching, filtering and moving),
+//! [`pattern`] (target templates), [`placeholders`] (values of `#{...}` placeholders)
+//! and [`output`] (reports of moves).
+//! Modules `app` and `cli` are the command line front end of the `mmv` binary,
+//! they are not a part of the stable API.
+#[doc(hidden)]
+pub mod app;
+#[doc(hidden)]
+pub mod cli;
+pub mod config;
+mod encoding;
+pub mod errors;
+pub mod operations;
+pub mod output;
+pub mod pattern;
+pub mod placeholders;
+pub mod progress;
+pub mod rename;
+
+pub use config::Config;
+pub use errors::MassMoveError;
+pub use operations::file_move::MoveFiles;
+pub use rename::{Rename, RenamePlan};
@@ -1,48 +1,47 @@
-mod cli;
-mod config;
-mod errors;
-mod operations;
-mod pattern;
-
-use crate::errors::MassMoveError;
 use clap::Parser;
-use cli::parser::Args;
-use config::Config;
-use operations::file_matcher::FileMatcher;
-use operations::file_move::{FilesMover, MoveFiles};
-use pattern::insert_matches_in_target;
-use std::path::PathBuf;
+use mmv::app::run;
+use mmv::cli::parser::Args;
+use mmv::cli::{config_file, generate};
+use mmv::errors::{EX_IOERR, EX_USAGE};
+use mmv::output::{OutputFormat, Reporter, Verbosity};
+use mmv::Config;
+use std::io::{self, IsTerminal};
 use std::process;
 
-/// Function is wrapper for main function.
-/// It help separate logic from main function. Moreove, it make easier to handling errors in main function.
-fn run(args: Args, config: Config) -> Result<(), MassMoveError> {
-    let matcher = FileMatcher::from_source_path(PathBuf::from(args.source_path()))?;
-    let files_with_matches = matcher.get_files_with_matches()?;
-
-    let mut files_to_move: Vec<MoveFiles> = Vec::new();
-    for file_with_match in files_with_matches {
-        let target_filepath =
-            insert_matches_in_target(file_with_match.matches(), args.target_path())?;
-
-        files_to_move.push(MoveFiles {
-            from: file_with_match.filename().to_owned(),
-            to: target_filepath.clone(),
-        });
+fn main() {
+    let args = Args::try_parse().unwrap_or_else(|err| {
+        let _ = err.print();
+        process::exit(if err.use_stderr() { EX_USAGE } else { 0 });
+    });
+    if let Some(command) = args.subcommand() {
+        if let Err(err) = generate::run(command) {
+            eprintln!("mmv: {}", err);
+            process::exit(EX_IOERR);
+        }
+        return;
     }
 
-    FilesMover::new(config, files_to_move).run()
-}
-
-fn main() {
-    let args = Args::parse();
-    let config = Config::from_args(&args);
+    let (args, settings) = config_file::resolve(args).unwrap_or_else(|err| {
+        eprintln!("{}", err.display_chain());
+        process::exit(err.exit_code());
+    });
+    let config = Config::from_args(&args, &settings);
+    let progress = config.progress()
+        && config.output_format() == OutputFormat::Text
+        && config.verbosity() != Verbosity::Quiet
+        && io::stderr().is_terminal();
+    let reporter = Reporter::new(config.output_format(), config.dry_run())
+        .with_verbosity(config.verbosity())
+        .with_color(config.color().is_enabled())
+        .with_progress(progress);
 
-    match run(args, config) {
+    let result = run(args, config, &reporter);
+    reporter.finish(result.as_ref().err());
+    match result {
         Ok(_) => (),
         Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+            eprintln!("{}", e.display_chain());
+            process::exit(e.exit_code());
         }
     }
 }
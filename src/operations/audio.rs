@@ -0,0 +1,190 @@
+use super::file_system::FileSystem;
+use lofty::config::ParseOptions;
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+/// Names of placeholders of audio tags.
+pub const AUDIO_FIELDS: [&str; 7] = ["artist", "album", "title", "genre", "track", "disc", "year"];
+
+/// Names of placeholders of numeric audio tags, their argument is the width of the number.
+pub const NUMERIC_AUDIO_FIELDS: [&str; 3] = ["track", "disc", "year"];
+
+/// `AudioTags` are tags of an audio file used by placeholders of target templates:
+/// ID3 of MP3, Vorbis comments of FLAC and OGG and MP4 atoms of M4A files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub genre: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<u32>,
+}
+
+impl AudioTags {
+    /// Function returns the value of a tag by the name of its placeholder (see `AUDIO_FIELDS`).
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "artist" => self.artist.clone(),
+            "album" => self.album.clone(),
+            "title" => self.title.clone(),
+            "genre" => self.genre.clone(),
+            "track" => self.track.map(|track| track.to_string()),
+            "disc" => self.disc.map(|disc| disc.to_string()),
+            "year" => self.year.map(|year| year.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Function reads tags of an MP3, FLAC, OGG, M4A or another audio file supported by `lofty`.
+/// It returns `None` if the file is not an audio file or has no tags.
+pub fn read_audio_tags(file_system: &dyn FileSystem, path: &Path) -> io::Result<Option<AudioTags>> {
+    audio_tags_from_reader(BufReader::new(file_system.open(path)?))
+}
+
+/// Function reads tags from an audio file. Only the tag blocks are read, audio properties
+/// that need a scan of the audio stream are skipped, other files are recognized by the first bytes.
+fn audio_tags_from_reader<R: Read + Seek>(reader: R) -> io::Result<Option<AudioTags>> {
+    let probe = Probe::new(reader).options(ParseOptions::new().read_properties(false));
+    let Ok(tagged_file) = probe.guess_file_type()?.read() else {
+        return Ok(None);
+    };
+    let Some(tag) = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())
+    else {
+        return Ok(None);
+    };
+
+    let text = |value: Option<std::borrow::Cow<str>>| {
+        value
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    Ok(Some(AudioTags {
+        artist: text(tag.artist()),
+        album: text(tag.album()),
+        title: text(tag.title()),
+        genre: text(tag.genre()),
+        track: tag.track(),
+        disc: tag.disk(),
+        year: tag.date().map(|date| date.year.into()),
+    }))
+}
+
+#[test]
+fn test_read_audio_tags() {
+    use super::file_system::MemoryFileSystem;
+    use lofty::config::WriteOptions;
+    use lofty::id3::v2::Id3v2Tag;
+
+    let mut tag = Id3v2Tag::new();
+    tag.set_artist(String::from("AC/DC"));
+    tag.set_album(String::from(" Back in Black "));
+    tag.set_title(String::from("Hells Bells"));
+    tag.set_track(1);
+    let mut mp3 = Vec::new();
+    tag.dump_to(&mut mp3, WriteOptions::default()).unwrap();
+    // Two frames of MPEG-1 Layer III, 128 kbit/s, 44.1 kHz.
+    let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+    frame.resize(417, 0);
+    mp3.extend_from_slice(&frame);
+    mp3.extend_from_slice(&frame);
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("song.mp3", mp3);
+    fs.add_file("notes.txt", "no tags");
+
+    let cases: Vec<(&str, Option<AudioTags>)> = vec![
+        (
+            "song.mp3",
+            Some(AudioTags {
+                artist: Some(String::from("AC/DC")),
+                album: Some(String::from("Back in Black")),
+                title: Some(String::from("Hells Bells")),
+                track: Some(1),
+                ..AudioTags::default()
+            }),
+        ),
+        ("notes.txt", None),
+    ];
+
+    for case in cases {
+        let tags = read_audio_tags(&fs, Path::new(case.0)).unwrap();
+        assert_eq!(tags, case.1, "file: {}", case.0);
+    }
+}
+
+#[test]
+fn test_read_audio_tags_reads_only_tags() {
+    use std::io::SeekFrom;
+
+    /// Reader that counts read bytes.
+    struct CountingReader<R> {
+        inner: R,
+        count: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.inner.read(buf)?;
+            self.count += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    const SIZE: usize = 64 << 20;
+    // A FLAC file: the marker, a STREAMINFO block, a Vorbis comment block and the audio frames.
+    let mut flac = b"fLaC".to_vec();
+    flac.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+    flac.extend_from_slice(&[0x10, 0x00, 0x10, 0x00]);
+    flac.extend_from_slice(&[0; 6]);
+    flac.extend_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x00, 0x00, 0x00]);
+    flac.extend_from_slice(&[0; 16]);
+    let vendor = b"mmv";
+    let comment = b"ARTIST=Nick Cave";
+    let length = 4 + vendor.len() + 4 + 4 + comment.len();
+    flac.extend_from_slice(&[0x84, 0x00, 0x00, length as u8]);
+    flac.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    flac.extend_from_slice(vendor);
+    flac.extend_from_slice(&1u32.to_le_bytes());
+    flac.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+    flac.extend_from_slice(comment);
+    flac.resize(SIZE, 0);
+    let cases: Vec<(&str, Vec<u8>, Option<AudioTags>)> = vec![
+        ("video", vec![0; SIZE], None),
+        (
+            "flac",
+            flac,
+            Some(AudioTags {
+                artist: Some(String::from("Nick Cave")),
+                ..AudioTags::default()
+            }),
+        ),
+    ];
+
+    for case in cases {
+        let mut reader = CountingReader {
+            inner: io::Cursor::new(case.1),
+            count: 0,
+        };
+        let tags = audio_tags_from_reader(BufReader::new(&mut reader)).unwrap();
+        assert_eq!(tags, case.2, "file: {}", case.0);
+        assert!(
+            reader.count < 1 << 20,
+            "file: {}, read {} bytes",
+            case.0,
+            reader.count
+        );
+    }
+}
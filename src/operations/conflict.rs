@@ -0,0 +1,395 @@
+use super::file_move::MoveFiles;
+use super::file_system::{FileKind, FileSystem};
+use super::hash::{hash_file, HashAlgorithm};
+use crate::output::{MoveStatus, Reporter};
+use crate::{config::Config, errors::MassMoveError};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Placeholder of the number in a suffix format.
+const SUFFIX_NUMBER: &str = "{n}";
+
+/// Default suffix format, `name.txt` becomes `name (1).txt`.
+pub const DEFAULT_SUFFIX_FORMAT: &str = " ({n})";
+
+/// Policy for a target that already exists or is a target of an earlier pair,
+/// it is set with `--on-conflict`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum OnConflict {
+    /// The batch is not moved.
+    #[default]
+    Error,
+    /// The pair is not moved, it is reported as skipped.
+    Skip,
+    /// The existing target is replaced (as with `--force`), collisions are errors.
+    Overwrite,
+    /// A numeric suffix is added to the target name: `name (1).txt`.
+    Suffix,
+    /// The existing target is moved to a backup (`name.txt~`) first, collisions are errors.
+    Backup,
+}
+
+/// Policy for an existing target with the same size and hash as its source,
+/// it is set with `--on-duplicate`. Without it such targets are conflicts.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum OnDuplicate {
+    /// The pair is not moved, it is reported as skipped.
+    Skip,
+    /// The source is deleted after the batch is moved.
+    DeleteSource,
+    /// The source is replaced with a hard link to the target after the batch is moved.
+    Link,
+}
+
+/// `Resolution` is a planned batch with resolved conflicts.
+#[derive(Debug, Default, PartialEq)]
+pub struct Resolution {
+    /// Pairs to move.
+    pub files_to_move: Vec<MoveFiles>,
+    /// Pairs whose target is a duplicate of the source, they are deleted or linked
+    /// with `OnDuplicate::DeleteSource` or `OnDuplicate::Link`.
+    pub duplicates: Vec<MoveFiles>,
+}
+
+/// `ConflictResolver` rewrites a planned batch according to the `OnConflict` policy,
+/// so the dry run and plan files show the final names.
+/// A target is in conflict if it is a target of an earlier pair or if it exists and is not
+/// a source of the batch (such targets are moved away first).
+/// Usage:
+/// ```rust
+/// use mmv::operations::conflict::{ConflictResolver, OnConflict};
+/// use mmv::operations::file_move::MoveFiles;
+/// use mmv::output::Reporter;
+/// use mmv::Config;
+/// use std::path::PathBuf;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let config = Config::new()
+///     .with_on_conflict(OnConflict::Suffix)
+///     .with_suffix_format("-{n}");
+/// let files_to_move = vec![MoveFiles::new("a.txt", "b.txt"), MoveFiles::new("c.txt", "b.txt")];
+/// let resolved = ConflictResolver::new(&config)?.resolve(files_to_move, &Reporter::silent(true));
+/// assert_eq!(resolved.files_to_move[1].to, PathBuf::from("b-1.txt"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ConflictResolver<'a> {
+    config: &'a Config,
+    /// Suffix format split around `{n}`.
+    suffix: (&'a str, &'a str),
+}
+
+impl<'a> ConflictResolver<'a> {
+    /// Construct a new `ConflictResolver`, the suffix format must contain `{n}` and no `/`.
+    pub fn new(config: &'a Config) -> Result<Self, MassMoveError> {
+        let format = config.suffix_format();
+        let suffix = format
+            .split_once(SUFFIX_NUMBER)
+            .filter(|(prefix, suffix)| !prefix.contains('/') && !suffix.contains('/'))
+            .ok_or_else(|| {
+                MassMoveError::InvalidArguments(format!(
+                    "suffix format must contain {} and no '/': {}",
+                    SUFFIX_NUMBER, format
+                ))
+            })?;
+        Ok(ConflictResolver { config, suffix })
+    }
+
+    /// Function resolves conflicts of the batch. Skipped pairs are reported as skipped,
+    /// with `OnConflict::Backup` moves of existing targets to backups are added before the pairs.
+    /// With `OnDuplicate` existing targets with the same content as their sources are taken
+    /// out of the batch before other policies are applied.
+    pub fn resolve(&self, files_to_move: Vec<MoveFiles>, reporter: &Reporter) -> Resolution {
+        let policy = self.config.on_conflict();
+        let on_duplicate = self.config.on_duplicate();
+        if matches!(policy, OnConflict::Error | OnConflict::Overwrite) && on_duplicate.is_none() {
+            return Resolution {
+                files_to_move,
+                duplicates: Vec::new(),
+            };
+        }
+
+        let mut sources: HashSet<PathBuf> = files_to_move
+            .iter()
+            .map(|file_pair| file_pair.from.clone())
+            .collect();
+        let mut taken: HashSet<PathBuf> = HashSet::new();
+        let mut resolved = Vec::with_capacity(files_to_move.len());
+        let mut duplicates = Vec::new();
+        for mut file_pair in files_to_move {
+            let is_taken = taken.contains(&file_pair.to);
+            let exists = file_pair.from != file_pair.to
+                && !sources.contains(&file_pair.to)
+                && self.config.file_system().exists(&file_pair.to);
+
+            if let Some(on_duplicate) = on_duplicate {
+                let file_system = self.config.file_system().as_ref();
+                if exists && !is_taken && is_duplicate(file_system, &file_pair.from, &file_pair.to)
+                {
+                    if on_duplicate == OnDuplicate::Skip {
+                        reporter.report(
+                            &file_pair.from,
+                            Some(&file_pair.to),
+                            MoveStatus::Skipped,
+                            None,
+                        );
+                    } else {
+                        duplicates.push(file_pair);
+                    }
+                    continue;
+                }
+            }
+
+            match policy {
+                OnConflict::Skip if is_taken || exists => {
+                    reporter.report(
+                        &file_pair.from,
+                        Some(&file_pair.to),
+                        MoveStatus::Skipped,
+                        None,
+                    );
+                    continue;
+                }
+                OnConflict::Suffix if is_taken || exists => {
+                    file_pair.to = (1..)
+                        .map(|n| self.with_suffix(&file_pair.to, n))
+                        .find(|candidate| !self.is_used(candidate, &sources, &taken))
+                        .unwrap();
+                }
+                OnConflict::Backup if !is_taken && exists => {
+                    let backup = (0..)
+                        .map(|n| backup_path(&file_pair.to, n))
+                        .find(|candidate| !self.is_used(candidate, &sources, &taken))
+                        .unwrap();
+                    taken.insert(backup.clone());
+                    sources.insert(file_pair.to.clone());
+                    resolved.push(MoveFiles::new(file_pair.to.clone(), backup));
+                }
+                _ => {}
+            }
+            taken.insert(file_pair.to.clone());
+            resolved.push(file_pair);
+        }
+        Resolution {
+            files_to_move: resolved,
+            duplicates,
+        }
+    }
+
+    /// Function checks if a generated name is a source, a target or an existing file.
+    fn is_used(&self, path: &Path, sources: &HashSet<PathBuf>, taken: &HashSet<PathBuf>) -> bool {
+        sources.contains(path) || taken.contains(path) || self.config.file_system().exists(path)
+    }
+
+    /// Function adds the numbered suffix to the file name before its extension:
+    /// `dir/name.txt` becomes `dir/name (1).txt`.
+    fn with_suffix(&self, path: &Path, n: usize) -> PathBuf {
+        let (prefix, suffix) = self.suffix;
+        let mut file_name = OsString::new();
+        if let Some(stem) = path.file_stem() {
+            file_name.push(stem);
+        }
+        file_name.push(format!("{}{}{}", prefix, n, suffix));
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        path.with_file_name(file_name)
+    }
+}
+
+/// Function checks if two files have the same content: sizes are compared first,
+/// then BLAKE3 hashes. Files that can not be read are not duplicates.
+pub fn is_duplicate(file_system: &dyn FileSystem, first: &Path, second: &Path) -> bool {
+    let (Ok(first_metadata), Ok(second_metadata)) =
+        (file_system.metadata(first), file_system.metadata(second))
+    else {
+        return false;
+    };
+    if first_metadata.kind != FileKind::File
+        || second_metadata.kind != FileKind::File
+        || first_metadata.len != second_metadata.len
+    {
+        return false;
+    }
+    let hash = |path| hash_file(file_system, path, HashAlgorithm::Blake3).ok();
+    matches!((hash(first), hash(second)), (Some(first), Some(second)) if first == second)
+}
+
+/// Function returns a backup name of the file: `name.txt~` or a numbered one, `name.txt.~1~`.
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    if n == 0 {
+        backup.push("~");
+    } else {
+        backup.push(format!(".~{}~", n));
+    }
+    PathBuf::from(backup)
+}
+
+#[test]
+fn test_resolve_conflicts() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (OnConflict, Vec<(&'a str, &'a str)>, Vec<(&'a str, &'a str)>);
+    let cases: Vec<Case> = vec![
+        (
+            OnConflict::Error,
+            vec![("a.txt", "x.txt"), ("b.txt", "x.txt")],
+            vec![("a.txt", "x.txt"), ("b.txt", "x.txt")],
+        ),
+        (
+            OnConflict::Skip,
+            vec![("a.txt", "x.txt"), ("b.txt", "x.txt"), ("c.txt", "d.txt")],
+            vec![("a.txt", "x.txt")],
+        ),
+        (
+            OnConflict::Suffix,
+            vec![("a.txt", "x.txt"), ("b.txt", "x.txt"), ("c.txt", "d.txt")],
+            vec![
+                ("a.txt", "x.txt"),
+                ("b.txt", "x-2.txt"),
+                ("c.txt", "d-1.txt"),
+            ],
+        ),
+        (
+            OnConflict::Suffix,
+            vec![("a.txt", "c.txt"), ("b.txt", ".rc"), ("c.txt", ".rc")],
+            vec![("a.txt", "c.txt"), ("b.txt", ".rc"), ("c.txt", ".rc-1")],
+        ),
+        (
+            OnConflict::Backup,
+            vec![("a.txt", "d.txt"), ("b.txt", "x-1.txt"), ("c.txt", "d.txt")],
+            vec![
+                ("d.txt", "d.txt.~1~"),
+                ("a.txt", "d.txt"),
+                ("x-1.txt", "x-1.txt~"),
+                ("b.txt", "x-1.txt"),
+                ("c.txt", "d.txt"),
+            ],
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        for file in ["a.txt", "b.txt", "c.txt", "d.txt", "d.txt~", "x-1.txt"] {
+            fs.add_file(file, "");
+        }
+        let config = Config::new()
+            .with_file_system(fs)
+            .with_on_conflict(case.0)
+            .with_suffix_format("-{n}");
+        let files_to_move = case
+            .1
+            .iter()
+            .map(|(from, to)| MoveFiles::new(*from, *to))
+            .collect();
+        let resolved = ConflictResolver::new(&config)
+            .unwrap()
+            .resolve(files_to_move, &Reporter::silent(true))
+            .files_to_move;
+        let expected: Vec<MoveFiles> = case
+            .2
+            .iter()
+            .map(|(from, to)| MoveFiles::new(*from, *to))
+            .collect();
+        assert_eq!(resolved, expected, "policy: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_invalid_suffix_format() {
+    let cases: Vec<(&str, bool)> = vec![
+        (" ({n})", true),
+        ("-{n}", true),
+        ("-n", false),
+        ("/{n}", false),
+    ];
+
+    for case in cases {
+        let config = Config::new().with_suffix_format(case.0);
+        assert_eq!(
+            ConflictResolver::new(&config).is_ok(),
+            case.1,
+            "format: {}",
+            case.0
+        );
+    }
+}
+
+#[test]
+fn test_resolve_duplicates() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (
+        OnDuplicate,
+        OnConflict,
+        Vec<(&'a str, &'a str)>,
+        Vec<(&'a str, &'a str)>,
+        Vec<(&'a str, &'a str)>,
+    );
+    let cases: Vec<Case> = vec![
+        (
+            OnDuplicate::Skip,
+            OnConflict::Error,
+            vec![("a.txt", "x.txt"), ("b.txt", "y.txt"), ("c.txt", "z.txt")],
+            vec![("b.txt", "y.txt"), ("c.txt", "z.txt")],
+            vec![],
+        ),
+        (
+            OnDuplicate::DeleteSource,
+            OnConflict::Suffix,
+            vec![("a.txt", "x.txt"), ("b.txt", "y.txt")],
+            vec![("b.txt", "y-1.txt")],
+            vec![("a.txt", "x.txt")],
+        ),
+        (
+            OnDuplicate::Link,
+            OnConflict::Error,
+            vec![("b.txt", "x.txt"), ("a.txt", "x.txt")],
+            vec![("b.txt", "x.txt"), ("a.txt", "x.txt")],
+            vec![],
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        for (file, content) in [
+            ("a.txt", "same"),
+            ("b.txt", "size"),
+            ("c.txt", "new"),
+            ("x.txt", "same"),
+            ("y.txt", "diff"),
+        ] {
+            fs.add_file(file, content);
+        }
+        let config = Config::new()
+            .with_file_system(fs)
+            .with_on_duplicate(case.0)
+            .with_on_conflict(case.1)
+            .with_suffix_format("-{n}");
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<MoveFiles> {
+            pairs
+                .iter()
+                .map(|(from, to)| MoveFiles::new(*from, *to))
+                .collect()
+        };
+        let resolution = ConflictResolver::new(&config)
+            .unwrap()
+            .resolve(pairs(&case.2), &Reporter::silent(true));
+        let expected = Resolution {
+            files_to_move: pairs(&case.3),
+            duplicates: pairs(&case.4),
+        };
+        assert_eq!(resolution, expected, "policy: {:?}", case.0);
+    }
+}
@@ -0,0 +1,182 @@
+use super::file_move::MoveFiles;
+use crate::encoding::{escape_bytes, os_str_bytes, os_string_from_bytes};
+use crate::errors::{MassMoveError, Operation};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use tempdir::TempDir;
+
+/// `EditedList` is a result of editing a list of files:
+/// pairs of files to move for changed lines and files to delete for emptied lines.
+pub struct EditedList {
+    pub files_to_move: Vec<MoveFiles>,
+    pub files_to_delete: Vec<PathBuf>,
+}
+
+/// Function writes file names into a temporary file (one per line), opens it in the editor
+/// and turns changed lines into pairs of files to move.
+/// The editor is a command with arguments (e.g. `vim` or `code --wait`), the path of the
+/// temporary file is appended as the last argument.
+/// Usage:
+/// ```rust,no_run
+/// use mmv::operations::edit::edit_file_list;
+/// use std::path::PathBuf;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let files = vec![PathBuf::from("./notes/a.txt")];
+/// let edited = edit_file_list(&files, "vim", false)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn edit_file_list(
+    files: &[PathBuf],
+    editor: &str,
+    allow_delete: bool,
+) -> Result<EditedList, MassMoveError> {
+    let mut content = Vec::new();
+    for file in files {
+        let bytes = os_str_bytes(file.as_os_str());
+        if bytes.contains(&b'\n') {
+            return Err(MassMoveError::EditorError(format!(
+                "file name contains a newline: {}",
+                escape_bytes(bytes)
+            )));
+        }
+        content.extend_from_slice(bytes);
+        content.push(b'\n');
+    }
+
+    let directory = TempDir::new("mmv-edit").map_err(|source| MassMoveError::Io {
+        operation: Operation::CreateTempDirectory,
+        path: env::temp_dir(),
+        source,
+    })?;
+    let list_path = directory.path().join("files.txt");
+    fs::write(&list_path, &content).map_err(|source| MassMoveError::Io {
+        operation: Operation::WriteFile,
+        path: list_path.clone(),
+        source,
+    })?;
+
+    let mut command = editor.split_whitespace();
+    let Some(program) = command.next() else {
+        return Err(MassMoveError::EditorError("editor is not set".to_owned()));
+    };
+    let status = Command::new(program)
+        .args(command)
+        .arg(&list_path)
+        .status()
+        .map_err(|err| MassMoveError::EditorError(format!("{}: {}", editor, err)))?;
+    if !status.success() {
+        return Err(MassMoveError::EditorError(format!(
+            "{}: exited with {}",
+            editor, status
+        )));
+    }
+
+    let edited = fs::read(&list_path).map_err(|source| MassMoveError::Io {
+        operation: Operation::ReadFile,
+        path: list_path.clone(),
+        source,
+    })?;
+    diff_file_list(files, &edited, allow_delete)
+}
+
+/// Function compares the edited list with the original one line by line.
+/// Unchanged lines are skipped, changed lines become moves and empty lines become deletions
+/// (an error unless `allow_delete`). The number of lines must not change.
+fn diff_file_list(
+    files: &[PathBuf],
+    edited: &[u8],
+    allow_delete: bool,
+) -> Result<EditedList, MassMoveError> {
+    let edited = edited.strip_suffix(b"\n").unwrap_or(edited);
+    let lines: Vec<&[u8]> = edited.split(|&byte| byte == b'\n').collect();
+
+    if lines.len() != files.len() {
+        return Err(MassMoveError::InvalidEditedList(format!(
+            "expected {} lines, found {}; lines must not be added or removed",
+            files.len(),
+            lines.len()
+        )));
+    }
+
+    let mut edited_list = EditedList {
+        files_to_move: Vec::new(),
+        files_to_delete: Vec::new(),
+    };
+    for (i, (file, line)) in files.iter().zip(lines).enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            if !allow_delete {
+                return Err(MassMoveError::InvalidEditedList(format!(
+                    "line {} is empty, use --allow-delete to delete {}",
+                    i + 1,
+                    file.display()
+                )));
+            }
+            edited_list.files_to_delete.push(file.clone());
+            continue;
+        }
+
+        if line != os_str_bytes(file.as_os_str()) {
+            edited_list.files_to_move.push(MoveFiles {
+                from: file.clone(),
+                to: PathBuf::from(os_string_from_bytes(line.to_vec())?),
+            });
+        }
+    }
+    Ok(edited_list)
+}
+
+#[test]
+fn test_diff_file_list() {
+    type Case<'a> = (
+        &'a [u8],
+        bool,
+        Option<(Vec<(&'a str, &'a str)>, Vec<&'a str>)>,
+    );
+    let files = vec![PathBuf::from("a.txt"), PathBuf::from("b.txt")];
+    let cases: Vec<Case> = vec![
+        (b"a.txt\nb.txt\n", false, Some((vec![], vec![]))),
+        (
+            b"a.txt\nc.txt\n",
+            false,
+            Some((vec![("b.txt", "c.txt")], vec![])),
+        ),
+        (
+            b"x.txt\r\ny.txt",
+            false,
+            Some((vec![("a.txt", "x.txt"), ("b.txt", "y.txt")], vec![])),
+        ),
+        (b"\nb.txt\n", false, None),
+        (
+            b"\nc.txt\n",
+            true,
+            Some((vec![("b.txt", "c.txt")], vec!["a.txt"])),
+        ),
+        (b"a.txt\n", false, None),
+        (b"a.txt\nb.txt\nc.txt\n", false, None),
+    ];
+
+    for case in cases {
+        let result = diff_file_list(&files, case.0, case.1).ok().map(|edited| {
+            let moves: Vec<(PathBuf, PathBuf)> = edited
+                .files_to_move
+                .into_iter()
+                .map(|pair| (pair.from, pair.to))
+                .collect();
+            (moves, edited.files_to_delete)
+        });
+        let expected = case.2.map(|(moves, deletes)| {
+            let moves: Vec<(PathBuf, PathBuf)> = moves
+                .iter()
+                .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+                .collect();
+            let deletes: Vec<PathBuf> = deletes.iter().map(PathBuf::from).collect();
+            (moves, deletes)
+        });
+        assert_eq!(result, expected, "edited: {:?}", case.0);
+    }
+}
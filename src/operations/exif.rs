@@ -0,0 +1,204 @@
+use super::file_system::FileSystem;
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{Exif, In, Reader, Tag, Value};
+use std::io::{self, BufRead, BufReader, Seek};
+use std::path::Path;
+
+/// Names of placeholders of EXIF fields, except `#{date}` that falls back to the mtime.
+pub const EXIF_FIELDS: [&str; 5] = ["camera", "make", "lens", "width", "height"];
+
+/// `ExifFields` are EXIF fields of an image used by placeholders of target templates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExifFields {
+    /// Capture date: `DateTimeOriginal`, `DateTimeDigitized` or `DateTime`.
+    pub date_time: Option<NaiveDateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ExifFields {
+    /// Function builds the fields from parsed EXIF data.
+    fn from_exif(exif: &Exif) -> Self {
+        let ascii = |tag| match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+            Some(Value::Ascii(values)) => values
+                .first()
+                .map(|value| {
+                    String::from_utf8_lossy(value)
+                        .trim_matches(['\0', ' '])
+                        .to_owned()
+                })
+                .filter(|value| !value.is_empty()),
+            _ => None,
+        };
+        let uint = |tag| {
+            exif.get_field(tag, In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        };
+        let date_time = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime]
+            .into_iter()
+            .find_map(|tag| parse_date_time(ascii(tag)?.as_bytes()));
+
+        ExifFields {
+            date_time,
+            make: ascii(Tag::Make),
+            model: ascii(Tag::Model),
+            lens: ascii(Tag::LensModel),
+            width: uint(Tag::PixelXDimension).or_else(|| uint(Tag::ImageWidth)),
+            height: uint(Tag::PixelYDimension).or_else(|| uint(Tag::ImageLength)),
+        }
+    }
+
+    /// Function returns the value of a field by the name of its placeholder (see `EXIF_FIELDS`).
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "camera" => self.model.clone(),
+            "make" => self.make.clone(),
+            "lens" => self.lens.clone(),
+            "width" => self.width.map(|width| width.to_string()),
+            "height" => self.height.map(|height| height.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Function reads EXIF fields of a JPEG, TIFF, HEIF/HEIC, PNG or WebP image.
+/// It returns `None` if the file is not an image or has no EXIF data.
+pub fn read_exif_fields(
+    file_system: &dyn FileSystem,
+    path: &Path,
+) -> io::Result<Option<ExifFields>> {
+    exif_fields_from_reader(&mut BufReader::new(file_system.open(path)?))
+}
+
+/// Function reads EXIF fields from an image. Only the header and the segments before
+/// the EXIF data are read, other files are recognized by the first bytes.
+fn exif_fields_from_reader<R: BufRead + Seek>(reader: &mut R) -> io::Result<Option<ExifFields>> {
+    match Reader::new().read_from_container(reader) {
+        Ok(exif) => Ok(Some(ExifFields::from_exif(&exif))),
+        Err(exif::Error::Io(err)) if err.kind() != io::ErrorKind::UnexpectedEof => Err(err),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Function parses an EXIF date, e.g. `2024:10:08 17:30:00`. Blank and invalid dates are `None`.
+fn parse_date_time(value: &[u8]) -> Option<NaiveDateTime> {
+    let date_time = exif::DateTime::from_ascii(value).ok()?;
+    NaiveDate::from_ymd_opt(
+        date_time.year.into(),
+        date_time.month.into(),
+        date_time.day.into(),
+    )?
+    .and_hms_opt(
+        date_time.hour.into(),
+        date_time.minute.into(),
+        date_time.second.into(),
+    )
+}
+
+#[test]
+fn test_read_exif_fields() {
+    use super::file_system::MemoryFileSystem;
+    use exif::experimental::Writer;
+    use exif::Field;
+
+    let fields = [
+        Field {
+            tag: Tag::Model,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"X100V".to_vec()]),
+        },
+        Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2024:10:08 17:30:05".to_vec()]),
+        },
+        Field {
+            tag: Tag::DateTime,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2025:01:01 00:00:00".to_vec()]),
+        },
+        Field {
+            tag: Tag::PixelXDimension,
+            ifd_num: In::PRIMARY,
+            value: Value::Long(vec![6240]),
+        },
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = io::Cursor::new(Vec::new());
+    writer.write(&mut tiff, false).unwrap();
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("photo.tif", tiff.into_inner());
+    fs.add_file("notes.txt", "no exif");
+
+    let cases: Vec<(&str, Option<ExifFields>)> = vec![
+        (
+            "photo.tif",
+            Some(ExifFields {
+                date_time: NaiveDate::from_ymd_opt(2024, 10, 8)
+                    .and_then(|date| date.and_hms_opt(17, 30, 5)),
+                model: Some(String::from("X100V")),
+                width: Some(6240),
+                ..ExifFields::default()
+            }),
+        ),
+        ("notes.txt", None),
+    ];
+
+    for case in cases {
+        let fields = read_exif_fields(&fs, Path::new(case.0)).unwrap();
+        assert_eq!(fields, case.1, "file: {}", case.0);
+    }
+}
+
+#[test]
+fn test_read_exif_fields_reads_only_header() {
+    use std::io::{Read, SeekFrom};
+
+    /// Reader that counts read bytes.
+    struct CountingReader<R> {
+        inner: R,
+        count: u64,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.inner.read(buf)?;
+            self.count += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    const SIZE: usize = 64 << 20;
+    // A JPEG without EXIF data: the start of image, a comment segment and the image data.
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xFE, 0x00, 0x04, b'h', b'i', 0xFF, 0xDA];
+    jpeg.resize(SIZE, 0);
+    let cases: Vec<(&str, Vec<u8>)> = vec![("video", vec![0; SIZE]), ("jpeg", jpeg)];
+
+    for case in cases {
+        let mut reader = BufReader::new(CountingReader {
+            inner: io::Cursor::new(case.1),
+            count: 0,
+        });
+        assert_eq!(
+            exif_fields_from_reader(&mut reader).unwrap(),
+            None,
+            "file: {}",
+            case.0
+        );
+        let count = reader.into_inner().count;
+        assert!(count < 1 << 20, "file: {}, read {} bytes", case.0, count);
+    }
+}
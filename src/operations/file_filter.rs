@@ -0,0 +1,369 @@
+use super::file_system::FileMetadata;
+use crate::encoding::os_str_bytes;
+use crate::{config::Config, errors::MassMoveError};
+use fnmatch_regex::glob_to_regex;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use regex::bytes::{Regex, RegexBuilder};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Names of ignore files that are respected with `--ignore-files`.
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".mmvignore"];
+
+/// `PermFilter` is a permission predicate, it follows the `find -perm` syntax:
+/// - `644` - permission bits are exactly `644`;
+/// - `-644` - all of the bits `644` are set;
+/// - `/022` - any of the bits `022` is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PermFilter {
+    Exact(u32),
+    All(u32),
+    Any(u32),
+}
+
+/// `MetadataFilter` holds predicates that are evaluated from metadata of a matched file:
+/// size bounds, modification age, permissions and emptiness.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct MetadataFilter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub older_than: Option<Duration>,
+    pub newer_than: Option<Duration>,
+    pub perm: Option<PermFilter>,
+    pub empty: bool,
+}
+
+/// `FileFilter` is a filter stage of `FileMatcher`: it rejects matched files before they are moved.
+/// A file is rejected if its name matches one of the `--exclude` glob patterns or,
+/// with `--ignore-files`, if it is ignored by `.gitignore`, `.ignore` or `.mmvignore` files
+/// of the source directory or its parent directories (up to the root of a git repository).
+/// Files that pass the name checks are then checked by the `MetadataFilter` predicates.
+/// Usage:
+/// ```rust
+/// use mmv::operations::file_filter::FileFilter;
+/// use mmv::operations::file_matcher::FileMatcher;
+/// use mmv::Config;
+/// use std::path::{Path, PathBuf};
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let config = Config::new().with_exclude("*.tmp");
+/// let filter = FileFilter::from_config(&config, Path::new("./path/to"))?;
+/// let source_path = PathBuf::from("./path/to/*.txt");
+/// let matcher = FileMatcher::from_source_path(source_path)?.with_filter(filter);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct FileFilter {
+    excludes: Vec<ExcludePattern>,
+    source_directory: PathBuf,
+    ignores: Vec<Gitignore>,
+    metadata: MetadataFilter,
+    now: Option<SystemTime>,
+}
+
+/// `ExcludePattern` is a compiled exclude glob, it is matched on raw bytes of file names.
+/// Names that are valid UTF-8 are matched by characters, other names are matched by bytes,
+/// so `?` matches one invalid byte of them.
+struct ExcludePattern {
+    regex: Regex,
+    /// Regex without Unicode, it is `None` if the glob has non-ASCII character classes.
+    bytes_regex: Option<Regex>,
+}
+
+impl ExcludePattern {
+    /// Construct an `ExcludePattern` from a glob pattern.
+    fn new(pattern: &str) -> Result<Self, String> {
+        let regex = glob_to_regex(pattern).map_err(|err| err.to_string())?;
+        Ok(ExcludePattern {
+            regex: Regex::new(regex.as_str()).map_err(|err| err.to_string())?,
+            bytes_regex: RegexBuilder::new(regex.as_str())
+                .unicode(false)
+                .build()
+                .ok(),
+        })
+    }
+
+    /// Function checks if a file name matches the pattern.
+    fn is_match(&self, filename: &OsStr) -> bool {
+        let name = os_str_bytes(filename);
+        if std::str::from_utf8(name).is_ok() {
+            self.regex.is_match(name)
+        } else {
+            self.bytes_regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(name))
+        }
+    }
+}
+
+impl FromStr for PermFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (constructor, mode): (fn(u32) -> PermFilter, &str) =
+            if let Some(mode) = value.strip_prefix('-') {
+                (PermFilter::All, mode)
+            } else if let Some(mode) = value.strip_prefix('/') {
+                (PermFilter::Any, mode)
+            } else {
+                (PermFilter::Exact, value)
+            };
+
+        match u32::from_str_radix(mode, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(constructor(mode)),
+            _ => Err(format!("invalid octal mode: {}", value)),
+        }
+    }
+}
+
+impl PermFilter {
+    /// Function checks permission bits of a file.
+    fn is_match(&self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match *self {
+            PermFilter::Exact(bits) => mode == bits,
+            PermFilter::All(bits) => mode & bits == bits,
+            PermFilter::Any(bits) => bits == 0 || mode & bits != 0,
+        }
+    }
+}
+
+impl MetadataFilter {
+    /// Function returns true if at least one predicate is set, so metadata has to be read.
+    pub fn is_active(&self) -> bool {
+        self.min_size.is_some()
+            || self.max_size.is_some()
+            || self.older_than.is_some()
+            || self.newer_than.is_some()
+            || self.perm.is_some()
+            || self.empty
+    }
+
+    /// Function checks metadata of a file against all predicates.
+    /// `now` is a moment the age of the file is counted from.
+    fn is_match(&self, metadata: &FileMetadata, now: SystemTime) -> bool {
+        let size = metadata.len;
+        if self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max)
+            || (self.empty && size != 0)
+        {
+            return false;
+        }
+
+        if self.older_than.is_some() || self.newer_than.is_some() {
+            let Some(modified) = metadata.modified else {
+                return false;
+            };
+            let age = now.duration_since(modified).unwrap_or_default();
+            if self.older_than.is_some_and(|older| age < older)
+                || self.newer_than.is_some_and(|newer| age > newer)
+            {
+                return false;
+            }
+        }
+
+        match self.perm {
+            Some(perm) => perm.is_match(metadata.mode),
+            None => true,
+        }
+    }
+}
+
+impl FileFilter {
+    /// Construct a new `FileFilter` that accepts all files.
+    pub fn new() -> Self {
+        FileFilter::default()
+    }
+
+    /// Construct a new `FileFilter` from the exclude patterns, ignore files and metadata settings of the config.
+    pub fn from_config(config: &Config, source_directory: &Path) -> Result<Self, MassMoveError> {
+        let mut filter = FileFilter::new();
+        for pattern in config.excludes() {
+            filter.add_exclude(pattern)?;
+        }
+        if config.ignore_files() {
+            filter.load_ignore_files(source_directory)?;
+        }
+        filter.metadata = config.metadata_filter().clone();
+        filter.now = Some(SystemTime::now());
+        Ok(filter)
+    }
+
+    /// Function adds a glob pattern, files with matched names are excluded.
+    fn add_exclude(&mut self, pattern: &str) -> Result<(), MassMoveError> {
+        match ExcludePattern::new(pattern) {
+            Ok(exclude) => {
+                self.excludes.push(exclude);
+                Ok(())
+            }
+            Err(err) => Err(MassMoveError::InvalidExcludePattern(format!(
+                "{}: {}",
+                pattern, err
+            ))),
+        }
+    }
+
+    /// Function loads ignore files from the source directory and its parents.
+    /// Parents are visited up to a directory with `.git`, it is the root of a repository.
+    fn load_ignore_files(&mut self, source_directory: &Path) -> Result<(), MassMoveError> {
+        let read_path = if source_directory.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            source_directory
+        };
+        let Ok(source_directory) = read_path.canonicalize() else {
+            return Err(MassMoveError::DirectoryNotFound(
+                source_directory.display().to_string(),
+            ));
+        };
+
+        for directory in source_directory.ancestors() {
+            let mut builder = GitignoreBuilder::new(directory);
+            for name in IGNORE_FILES {
+                let path = directory.join(name);
+                if !path.is_file() {
+                    continue;
+                }
+                if let Some(err) = builder.add(&path) {
+                    return Err(MassMoveError::InvalidExcludePattern(format!(
+                        "{}: {}",
+                        path.display(),
+                        err
+                    )));
+                }
+            }
+
+            match builder.build() {
+                Ok(gitignore) if !gitignore.is_empty() => self.ignores.push(gitignore),
+                Ok(_) => {}
+                Err(err) => return Err(MassMoveError::InvalidExcludePattern(err.to_string())),
+            }
+
+            if directory.join(".git").exists() {
+                break;
+            }
+        }
+
+        self.source_directory = source_directory;
+        Ok(())
+    }
+
+    /// Function checks if a file of the source directory is excluded.
+    /// Ignore files of nested directories take precedence over ignore files of their parents.
+    pub fn is_excluded(&self, filename: &OsStr) -> bool {
+        if self
+            .excludes
+            .iter()
+            .any(|exclude| exclude.is_match(filename))
+        {
+            return true;
+        }
+
+        let path = self.source_directory.join(filename);
+        for gitignore in &self.ignores {
+            match gitignore.matched_path_or_any_parents(&path, false) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// Function returns true if the filter has metadata predicates.
+    pub fn needs_metadata(&self) -> bool {
+        self.metadata.is_active()
+    }
+
+    /// Function checks metadata of a file that passed the name checks.
+    pub fn is_accepted(&self, metadata: &FileMetadata) -> bool {
+        let now = self.now.unwrap_or_else(SystemTime::now);
+        self.metadata.is_match(metadata, now)
+    }
+}
+
+#[test]
+fn test_is_excluded() {
+    let cases: Vec<(Vec<&str>, &str, bool)> = vec![
+        (vec!["*.tmp"], "file.tmp", true),
+        (vec!["*.tmp"], "file.txt", false),
+        (vec!["*.tmp", "*.bak"], "file.bak", true),
+        (vec!["file-?.txt"], "file-1.txt", true),
+        (vec!["file-?.txt"], "file-10.txt", false),
+        (vec!["file-?.txt"], "file-é.txt", true),
+        (vec![], "file.tmp", false),
+    ];
+
+    for case in cases {
+        let mut filter = FileFilter::new();
+        for pattern in &case.0 {
+            filter.add_exclude(pattern).unwrap();
+        }
+        assert_eq!(
+            filter.is_excluded(OsStr::new(case.1)),
+            case.2,
+            "excludes: {:?}, file: {}",
+            case.0,
+            case.1
+        );
+    }
+}
+
+#[test]
+fn test_perm_filter() {
+    let cases: Vec<(&str, u32, bool)> = vec![
+        ("644", 0o644, true),
+        ("644", 0o600, false),
+        ("-600", 0o644, true),
+        ("-600", 0o400, false),
+        ("/022", 0o644, false),
+        ("/022", 0o664, true),
+        ("-4000", 0o4755, true),
+    ];
+
+    for case in cases {
+        let perm = PermFilter::from_str(case.0).unwrap();
+        assert_eq!(
+            perm.is_match(case.1),
+            case.2,
+            "perm: {}, mode: {:o}",
+            case.0,
+            case.1
+        );
+    }
+
+    assert!(PermFilter::from_str("999").is_err());
+    assert!(PermFilter::from_str("-rw").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_is_excluded_non_utf8() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let cases: Vec<(&str, &[u8], bool)> = vec![
+        ("*.tmp", b"file-\xE9.tmp", true),
+        ("*.tmp", b"file-\xE9.txt", false),
+        ("file-?.txt", b"file-\xE9.txt", true),
+        ("file-\u{FFFD}.txt", b"file-\xE9.txt", false),
+        ("[\u{e9}]*", b"\xE9.txt", false),
+    ];
+
+    for case in cases {
+        let mut filter = FileFilter::new();
+        filter.add_exclude(case.0).unwrap();
+        assert_eq!(
+            filter.is_excluded(&OsString::from_vec(case.1.to_vec())),
+            case.2,
+            "exclude: {}, file: {:?}",
+            case.0,
+            case.1
+        );
+    }
+}
@@ -1,44 +1,86 @@
-use crate::errors::MassMoveError;
-use regex::Regex;
+use super::file_filter::FileFilter;
+use super::file_system::{DirEntry, FileKind, FileSystem, ReadDir, RealFileSystem};
+use crate::encoding::{os_str_bytes, os_string_from_bytes};
+use crate::errors::{MassMoveError, Operation};
+use crate::progress::Progress;
+use rayon::prelude::*;
+use regex::bytes::{Captures, Regex, RegexBuilder};
 use regex_syntax::escape;
-use std::fs::{self, ReadDir};
+use std::ffi::{OsStr, OsString};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-/// `FileMatcher` is a struct that matches files in a directory based on a pattern and returns a vector of `FileWithMatches`
+/// `FileMatcher` is a struct that matches files in a directory based on a pattern and returns an iterator of `FileWithMatches`
 /// `FileMatcher` returns an error is no one file is matched for a pattern or if the source path is invalid.
+/// Matching is done on raw bytes of file names, so names that are not valid UTF-8 are supported.
+/// The pattern is compiled once, when the matcher is created.
 /// Usage:
 /// ```rust
-/// use crate::operations::file_matcher::FileMatcher;
+/// use mmv::operations::file_matcher::FileMatcher;
+/// use std::path::PathBuf;
+/// # use tempdir::TempDir;
 ///
-/// let example_source_path = "./path/to/*.txt"
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// # let directory = TempDir::new("mmv-doc")?;
+/// # std::fs::File::create(directory.path().join("notes.txt"))?;
+/// let example_source_path: PathBuf = directory.path().join("*.txt");
 /// let matcher = FileMatcher::from_source_path(example_source_path)?;
-/// let files_with_matches = matcher.get_files_with_matches()?;
+/// for file_with_matches in matcher.iter_files_with_matches()? {
+///     let file_with_matches = file_with_matches?;
+///     assert_eq!(file_with_matches.matches()[0], "notes");
+/// }
+/// # Ok(())
+/// # }
 /// ```
 pub struct FileMatcher {
-    source_pattern: String,
-    source_directory: String,
+    source_pattern: OsString,
+    source_directory: PathBuf,
+    regex: Regex,
+    filter: FileFilter,
+    allow_no_match: bool,
+    file_system: Arc<dyn FileSystem>,
+    progress: Arc<Progress>,
 }
 
 /// `FileWithMatches` is a struct that contains a filepath and a vector of matches for a file.
 /// Example of file_with_matches for a concrete file and pattern:
-/// ```rust
-/// use crate::operations::file_matcher::FileWithMatches;
-///
-/// let pattern = "file-*.*";
-///
-/// let file = FileWithMatches {
-///    filepath: "./path/to/file-new.txt".to_owned(),
-///    matches: vec!["new".to_owned(), "txt".to_owned()],
-/// };
+/// ```text
+/// pattern: "./path/to/file-*.*"
+/// file_with_matches.filename() == "./path/to/file-new.txt"
+/// file_with_matches.matches() == ["new", "txt"]
 /// ```
 pub struct FileWithMatches {
-    filepath: String,
-    matches: Vec<String>,
+    filepath: PathBuf,
+    matches: Vec<OsString>,
 }
 
-pub type FileMatcherResult = Vec<FileWithMatches>;
+/// `MatchedFiles` is a streaming iterator over files of a source directory that match the pattern.
+/// Directory entries are read lazily, one by one, so no intermediate list of files is built.
+/// If the directory has no matched files, the iterator yields a single `MassMoveError::NoFilesForPattern`
+/// (unless it is allowed with `FileMatcher::with_allow_no_match`).
+pub struct MatchedFiles<'a> {
+    matcher: &'a FileMatcher,
+    entries: ReadDir<'a>,
+    matched: usize,
+    finished: bool,
+}
 
 impl FileMatcher {
+    /// Private constructor that compiles the pattern.
+    fn new(source_directory: PathBuf, source_pattern: OsString) -> Self {
+        let regex = Self::build_regex(&source_pattern, false);
+        FileMatcher {
+            source_pattern,
+            source_directory,
+            regex,
+            filter: FileFilter::new(),
+            allow_no_match: false,
+            file_system: Arc::new(RealFileSystem),
+            progress: Arc::default(),
+        }
+    }
+
     /// Construct a new `FileMatcher` from a source path.
     /// Source path is a first command line argument.
     pub fn from_source_path(source_path: PathBuf) -> Result<Self, MassMoveError> {
@@ -47,110 +89,254 @@ impl FileMatcher {
 
         if file_name.is_none() || parent.is_none() {
             return Err(MassMoveError::InvalidSourcePath(
-                source_path.to_str().unwrap().to_owned(),
+                source_path.display().to_string(),
             ));
         }
 
-        Ok(Self {
-            source_pattern: file_name.unwrap().to_str().unwrap().to_owned(),
-            source_directory: parent.unwrap().to_str().unwrap().to_owned(),
-        })
+        Ok(Self::new(
+            parent.unwrap().to_owned(),
+            file_name.unwrap().to_owned(),
+        ))
     }
 
-    /// Function format input pattern to valid regex pattern.
-    /// It screens all speacial characters and then make from '*' a capture group.
-    fn pattern_to_regex(&self) -> String {
-        let escaped = escape(&self.source_pattern);
-        let regex_pattern = escaped.replace("\\*", "([^.]*)");
-        format!("^{}$", regex_pattern)
+    /// Function sets a filter stage, files rejected by the filter are skipped.
+    pub fn with_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
     }
 
-    /// Function checks if file matches the pattern.
-    fn is_file_match_pattern(&self, filename: &str) -> Result<bool, MassMoveError> {
-        let pattern = self.pattern_to_regex();
-        Ok(Regex::new(&pattern).unwrap().is_match(filename))
+    /// Function allows the pattern to match no files, `MassMoveError::NoFilesForPattern` is not returned then.
+    pub fn with_allow_no_match(mut self, allow_no_match: bool) -> Self {
+        self.allow_no_match = allow_no_match;
+        self
     }
 
-    /// Function returns a vector of all matches for a file.
-    fn get_file_matches(&self, filename: &str) -> Result<Vec<String>, MassMoveError> {
-        let mut matches = Vec::new();
-        let re = Regex::new(&self.pattern_to_regex()).unwrap();
+    /// Function makes the pattern case-insensitive (for ASCII letters).
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.regex = Self::build_regex(&self.source_pattern, ignore_case);
+        self
+    }
 
-        for caps in re.captures_iter(filename) {
-            for (i, cap) in caps.iter().enumerate() {
-                if let Some(cap) = cap {
-                    if i != 0 && cap.start() != cap.end() {
-                        matches.push(filename[cap.start()..cap.end()].to_owned());
-                    }
+    /// Function sets the file system the source directory is read from.
+    pub fn with_file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = file_system;
+        self
+    }
+
+    /// Function sets the progress that counts scanned and matched entries.
+    pub fn with_progress(mut self, progress: Arc<Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn source_directory(&self) -> &Path {
+        &self.source_directory
+    }
+
+    /// Function format input pattern to valid regex pattern.
+    /// It screens all speacial characters and then make from '*' a capture group.
+    /// Non-ASCII bytes are written as `\xNN`, so the regex matches raw bytes of a file name.
+    fn pattern_to_regex(source_pattern: &OsStr) -> String {
+        let mut regex_pattern = String::from("^");
+        for &byte in os_str_bytes(source_pattern) {
+            match byte {
+                b'*' => regex_pattern.push_str("([^.]*)"),
+                byte if byte.is_ascii() => {
+                    regex_pattern.push_str(&escape(&char::from(byte).to_string()))
                 }
+                byte => regex_pattern.push_str(&format!("\\x{:02X}", byte)),
             }
         }
+        regex_pattern.push('$');
+        regex_pattern
+    }
 
-        Ok(matches)
+    /// Function builds a byte regex from the source pattern.
+    fn build_regex(source_pattern: &OsStr, ignore_case: bool) -> Regex {
+        RegexBuilder::new(&Self::pattern_to_regex(source_pattern))
+            .unicode(false)
+            .case_insensitive(ignore_case)
+            .build()
+            .unwrap()
     }
 
-    /// Function try to read a source directory and return a std::fs::ReadDir object.
-    fn read_source_directory(&self) -> Result<ReadDir, MassMoveError> {
-        let mut read_path = self.source_directory.clone();
-        if read_path.is_empty() {
-            read_path = "./".to_owned();
+    /// Function copies non-empty captures of a matched file name.
+    fn extract_matches(caps: &Captures) -> Result<Vec<OsString>, MassMoveError> {
+        let mut matches = Vec::with_capacity(caps.len() - 1);
+        for cap in caps.iter().skip(1).flatten() {
+            if cap.start() != cap.end() {
+                matches.push(os_string_from_bytes(cap.as_bytes().to_vec())?);
+            }
         }
-        match fs::read_dir(read_path) {
-            Ok(read_directory) => Ok(read_directory),
-            Err(_) => Err(MassMoveError::DirectoryNotFound(
-                self.source_directory.clone(),
-            )),
+        Ok(matches)
+    }
+
+    /// Function matches a file name against the pattern and extracts matches in a single pass.
+    /// Returns `None` if the file name does not match the pattern.
+    #[cfg(test)]
+    fn match_filename(&self, filename: &OsStr) -> Result<Option<Vec<OsString>>, MassMoveError> {
+        match self.regex.captures(os_str_bytes(filename)) {
+            Some(caps) => Self::extract_matches(&caps).map(Some),
+            None => Ok(None),
         }
     }
 
-    /// Function collects all matched files from a source directory that match the pattern.
-    fn collect_matched_files(&self) -> Result<Vec<String>, MassMoveError> {
-        let mut files = Vec::new();
-        let directory = self.read_source_directory()?;
+    /// Function matches a directory entry.
+    /// The file name is checked first, so the file type and metadata are requested only for
+    /// matched entries that are not rejected by the filter. Matches are copied out of the
+    /// file name only after all filters passed.
+    fn match_entry(&self, entry: &DirEntry) -> Option<Result<FileWithMatches, MassMoveError>> {
+        self.progress.scanned(1);
+        let filename = entry.file_name();
+        let caps = self.regex.captures(os_str_bytes(filename))?;
 
-        for entry in directory.into_iter().filter_map(|e| e.ok()) {
-            if let Ok(file_type) = entry.file_type() {
-                if file_type.is_file() {
-                    let filename = entry.file_name().into_string().unwrap();
-                    if self.is_file_match_pattern(&filename)? {
-                        files.push(filename);
-                    }
-                }
+        if self.filter.is_excluded(filename) {
+            return None;
+        }
+
+        match entry.kind() {
+            Ok(FileKind::File) => {}
+            _ => return None,
+        }
+
+        if self.filter.needs_metadata() {
+            match entry.metadata() {
+                Ok(metadata) if self.filter.is_accepted(&metadata) => {}
+                _ => return None,
             }
         }
 
-        if files.is_empty() {
-            Err(MassMoveError::NoFilesForPattern(String::from(
-                &self.source_pattern,
-            )))
+        let matches = match Self::extract_matches(&caps) {
+            Ok(matches) => matches,
+            Err(err) => return Some(Err(err)),
+        };
+        self.progress.matched(1);
+        Some(Ok(FileWithMatches {
+            filepath: self.source_directory.join(filename),
+            matches,
+        }))
+    }
+
+    /// Function try to read a source directory and return a streaming listing of it.
+    /// `MassMoveError::DirectoryNotFound` is returned only if the directory does not exist,
+    /// other failures keep the original `io::Error`.
+    fn read_source_directory(&self) -> Result<ReadDir<'_>, MassMoveError> {
+        let mut read_path = self.source_directory.as_path();
+        if read_path.as_os_str().is_empty() {
+            read_path = Path::new("./");
+        }
+        self.file_system
+            .read_dir(read_path)
+            .map_err(|source| match source.kind() {
+                io::ErrorKind::NotFound => {
+                    MassMoveError::DirectoryNotFound(self.source_directory.display().to_string())
+                }
+                _ => self.read_directory_error(source),
+            })
+    }
+
+    /// Function builds an error of reading the source directory.
+    fn read_directory_error(&self, source: io::Error) -> MassMoveError {
+        MassMoveError::Io {
+            operation: Operation::ReadDirectory,
+            path: self.source_directory.clone(),
+            source,
+        }
+    }
+
+    /// Function returns a streaming iterator over files that match the pattern.
+    pub fn iter_files_with_matches(&self) -> Result<MatchedFiles<'_>, MassMoveError> {
+        Ok(MatchedFiles {
+            matcher: self,
+            entries: self.read_source_directory()?,
+            matched: 0,
+            finished: false,
+        })
+    }
+
+    /// Function matches files of the source directory on the rayon thread pool.
+    /// Entries are matched in parallel, but the result keeps the order of the directory listing.
+    /// All errors are aggregated in `MassMoveError::Multiple`.
+    pub fn par_files_with_matches(&self) -> Result<Vec<FileWithMatches>, MassMoveError> {
+        let entries: Vec<DirEntry> = self
+            .read_source_directory()?
+            .collect::<Result<_, _>>()
+            .map_err(|source| self.read_directory_error(source))?;
+        let results: Vec<Result<FileWithMatches, MassMoveError>> = entries
+            .par_iter()
+            .filter_map(|entry| self.match_entry(entry))
+            .collect();
+
+        let files = MassMoveError::collect(results)?;
+        if files.is_empty() && !self.allow_no_match {
+            Err(MassMoveError::NoFilesForPattern(
+                self.source_pattern.to_string_lossy().into_owned(),
+            ))
         } else {
             Ok(files)
         }
     }
 
-    /// Function returns a vector of `FileWithMatches` for all files that match the pattern.
-    pub fn get_files_with_matches(&self) -> Result<FileMatcherResult, MassMoveError> {
-        let mut result = Vec::new();
+    /// Function matches files of the source directory on the rayon thread pool and returns
+    /// a result for every matched entry, as `iter_files_with_matches` does, in the order of
+    /// the directory listing. It is used with `--keep-going`, errors of files do not stop others.
+    pub fn par_match_results(
+        &self,
+    ) -> Result<Vec<Result<FileWithMatches, MassMoveError>>, MassMoveError> {
+        let entries: Vec<io::Result<DirEntry>> = self.read_source_directory()?.collect();
+        let mut results: Vec<Result<FileWithMatches, MassMoveError>> = entries
+            .into_par_iter()
+            .filter_map(|entry| match entry {
+                Ok(entry) => self.match_entry(&entry),
+                Err(source) => Some(Err(self.read_directory_error(source))),
+            })
+            .collect();
 
-        for file in self.collect_matched_files()? {
-            let matches = self.get_file_matches(&file)?;
-            let filepath = Path::new(&self.source_directory)
-                .join(file)
-                .to_str()
-                .unwrap()
-                .to_owned();
-            result.push(FileWithMatches { matches, filepath })
+        if results.is_empty() && !self.allow_no_match {
+            results.push(Err(MassMoveError::NoFilesForPattern(
+                self.source_pattern.to_string_lossy().into_owned(),
+            )));
+        }
+        Ok(results)
+    }
+}
+
+impl Iterator for MatchedFiles<'_> {
+    type Item = Result<FileWithMatches, MassMoveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        for entry in self.entries.by_ref() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(source) => return Some(Err(self.matcher.read_directory_error(source))),
+            };
+            if let Some(file) = self.matcher.match_entry(&entry) {
+                self.matched += 1;
+                return Some(file);
+            }
+        }
+
+        self.finished = true;
+        if self.matched == 0 && !self.matcher.allow_no_match {
+            Some(Err(MassMoveError::NoFilesForPattern(
+                self.matcher.source_pattern.to_string_lossy().into_owned(),
+            )))
+        } else {
+            None
         }
-        Ok(result)
     }
 }
 
 impl FileWithMatches {
-    pub fn filename(&self) -> &str {
+    pub fn filename(&self) -> &Path {
         &self.filepath
     }
 
-    pub fn matches(&self) -> &Vec<String> {
+    pub fn matches(&self) -> &Vec<OsString> {
         &self.matches
     }
 }
@@ -187,8 +373,13 @@ fn test_match_initizalizer() {
         }
 
         let matcher = matcher.unwrap();
-        assert_eq!(matcher.source_pattern, case.1, "failed for: {}", case.0);
-        assert_eq!(matcher.source_directory, case.2, "failed for: {}", case.0);
+        assert_eq!(matcher.source_pattern, *case.1, "failed for: {}", case.0);
+        assert_eq!(
+            matcher.source_directory,
+            Path::new(case.2),
+            "failed for: {}",
+            case.0
+        );
     }
 }
 
@@ -208,13 +399,13 @@ fn test_file_match_pattern() {
     ];
 
     for case in cases {
-        let file_matcher = FileMatcher {
-            source_directory: String::from("./"),
-            source_pattern: String::from(case.1),
-        };
+        let file_matcher = FileMatcher::new(PathBuf::from("./"), OsString::from(case.1));
 
         assert_eq!(
-            file_matcher.is_file_match_pattern(case.0).unwrap(),
+            file_matcher
+                .match_filename(OsStr::new(case.0))
+                .unwrap()
+                .is_some(),
             case.2,
             "file: {}, pattern: {}",
             case.0,
@@ -225,31 +416,102 @@ fn test_file_match_pattern() {
 
 #[test]
 fn test_get_file_matches() {
-    let cases: Vec<(&str, &str, Vec<String>)> = vec![
+    let cases: Vec<(&str, &str, Vec<OsString>)> = vec![
         (
             "*file*.png",
             "some_file_1.png",
-            vec![String::from("some_"), String::from("_1")],
+            vec![OsString::from("some_"), OsString::from("_1")],
         ),
-        ("*file*.png", "file_2.png", vec![String::from("_2")]),
+        ("*file*.png", "file_2.png", vec![OsString::from("_2")]),
         ("*file*.png", "file.png", vec![]),
         (
             "*.*",
             "backend.tar",
-            vec![String::from("backend"), String::from("tar")],
+            vec![OsString::from("backend"), OsString::from("tar")],
+        ),
+        (
+            "файл-*.txt",
+            "файл-новый.txt",
+            vec![OsString::from("новый")],
         ),
     ];
 
     for case in cases {
-        let matcher = FileMatcher {
-            source_directory: "./".to_owned(),
-            source_pattern: case.0.to_owned(),
-        };
+        let matcher = FileMatcher::new(PathBuf::from("./"), OsString::from(case.0));
         assert_eq!(
-            matcher.get_file_matches(case.1).unwrap(),
+            matcher.match_filename(OsStr::new(case.1)).unwrap().unwrap(),
             case.2,
             "failed get matches for: \"{}\"",
             case.1
         );
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn test_get_file_matches_non_utf8() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    type Case<'a> = (&'a [u8], &'a [u8], Vec<&'a [u8]>);
+    let cases: Vec<Case> = vec![
+        (b"file-*.txt", b"file-\xE9t\xE9.txt", vec![b"\xE9t\xE9"]),
+        (b"\xE9-*.*", b"\xE9-a.b", vec![b"a", b"b"]),
+        (b"*.txt", b"\xFF\xFE.txt", vec![b"\xFF\xFE"]),
+    ];
+
+    for case in cases {
+        let matcher = FileMatcher::new(PathBuf::from("./"), OsString::from_vec(case.0.to_vec()));
+        let expected: Vec<OsString> = case
+            .2
+            .iter()
+            .map(|m| OsString::from_vec(m.to_vec()))
+            .collect();
+        assert_eq!(
+            matcher
+                .match_filename(OsStr::from_bytes(case.1))
+                .unwrap()
+                .unwrap(),
+            expected,
+            "failed get matches for: {:?}",
+            case.1
+        );
+    }
+}
+
+#[test]
+fn test_match_on_memory_file_system() {
+    use super::file_system::MemoryFileSystem;
+
+    type Case<'a> = (&'a str, Option<Vec<&'a str>>);
+    let cases: Vec<Case> = vec![
+        (
+            "./notes/*.txt",
+            Some(vec!["./notes/a.txt", "./notes/b.txt"]),
+        ),
+        ("notes/a.*", Some(vec!["notes/a.txt"])),
+        ("./notes/*", None),
+        ("./notes/*.md", None),
+        ("./missing/*.txt", None),
+    ];
+
+    let fs = Arc::new(MemoryFileSystem::new());
+    fs.add_file("notes/a.txt", "a");
+    fs.add_file("notes/b.txt", "b");
+    fs.add_dir("notes/c.txt");
+    fs.add_dir("notes/dir");
+
+    for case in cases {
+        let matcher = FileMatcher::from_source_path(PathBuf::from(case.0))
+            .unwrap()
+            .with_file_system(fs.clone());
+        let files = matcher.iter_files_with_matches().and_then(|files| {
+            files
+                .map(|file| file.map(|file| file.filename().to_owned()))
+                .collect::<Result<Vec<PathBuf>, _>>()
+        });
+        let expected = case
+            .1
+            .map(|files| files.iter().map(PathBuf::from).collect::<Vec<_>>());
+        assert_eq!(files.ok(), expected, "pattern: {}", case.0);
+    }
+}
@@ -1,84 +1,1411 @@
+use super::conflict::OnDuplicate;
+use super::hash::{hash_file, HashAlgorithm};
+use crate::errors::Operation;
+use crate::output::{MoveStatus, Reporter};
+use crate::progress::Progress;
 use crate::{config::Config, errors::MassMoveError};
-use std::fs;
-use std::path::PathBuf;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Algorithm of checksums of `--verify`.
+const VERIFY_ALGORITHM: HashAlgorithm = HashAlgorithm::Blake3;
 
 /// Public struct that represents a pair of files to move.
+#[derive(Debug, Clone, PartialEq)]
 pub struct MoveFiles {
-    pub from: String,
-    pub to: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl MoveFiles {
+    /// Construct a new pair of files to move.
+    pub fn new(from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        MoveFiles {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
 }
 
 /// FilesMover is a struct that moves files from one location to another.
 /// It takes a `Config` struct and a vector of `MoveFiles` structs.
 /// Usage:
 /// ```rust
-/// use crate::file_move::{FilesMover, MoveFiles};
-/// use crate::config::Config;
-/// ...
+/// use mmv::operations::file_move::{FilesMover, MoveFiles};
+/// use mmv::output::{OutputFormat, Reporter};
+/// use mmv::{Config, MassMoveError};
+/// # use tempdir::TempDir;
+///
+/// # fn main() -> Result<(), MassMoveError> {
+/// # let directory = TempDir::new("mmv-doc")?;
+/// # let from = directory.path().join("a.txt");
+/// # let to = directory.path().join("b.txt");
+/// # std::fs::File::create(&from)?;
 /// let config = Config::new();
-/// let files_to_move = FileMatcher(...)
+/// let files_to_move = vec![MoveFiles::new(from, to)];
 ///
-/// let result: Result<(), MassMoveError> = FilesMover::new(config, files_to_move).run();
+/// let reporter = Reporter::new(OutputFormat::Text, false);
+/// let result: Result<(), MassMoveError> = FilesMover::new(config, files_to_move).run(&reporter);
+/// # result
+/// # }
 /// ```
 ///
 /// Method run() drop the struct because we don't need to use files after moving
+///
+/// All moves are handled as one batch (transaction):
+/// 1. the batch is validated before any file is moved: targets must not collide with each other
+///    and must not replace existing files (unless `--force` or the file is moved away by the batch);
+/// 2. moves are scheduled so that a file is moved away before another file takes its place;
+/// 3. every done move is written to a journal, if a move fails all done moves are rolled back.
+///
+/// Unless `--force` is used, files are renamed with `FileSystem::rename_noreplace`, so a target
+/// created by another process after the validation is never replaced. Two files that are moved
+/// to each other's names (`a -> b`, `b -> a`) are swapped with `FileSystem::exchange`.
+///
+/// With `--dry-run` the batch is validated and scheduled, planned moves are printed but not done.
+///
+/// Every pair is reported to the `Reporter`: planned, moved, failed, skipped (not done because
+/// the batch is aborted) or rolled back.
+///
+/// With `--keep-going` invalid and failed moves are skipped instead of aborting the batch.
+///
+/// With `--jobs` the independent moves are done in parallel (see `independent_moves`),
+/// the moves that depend on each other are done after them one by one.
+///
+/// Files deleted by the batch (see `with_deletions`) are moved to hidden temporary files
+/// with the journal, so they are restored if the batch is rolled back. The temporary files
+/// are removed after the batch is moved.
 pub struct FilesMover {
     config: Config,
     files_to_move: Vec<MoveFiles>,
+    /// swaps[i] - the other move of a swap, the swap is done (and reported) by its first move.
+    swaps: Vec<Option<usize>>,
+    /// checksums[i] - the checksum of a file copied with `--verify`, it is reported with the move.
+    checksums: Vec<OnceLock<String>>,
+    /// Pairs whose target is a duplicate of the source, see `with_duplicates`.
+    duplicates: Vec<MoveFiles>,
+    /// deleted[i] - the move i moves a deleted file to a temporary file, see `with_deletions`.
+    deleted: Vec<bool>,
 }
 
 impl FilesMover {
     /// Create a new FilesMover struct from config and files to move
     pub fn new(config: Config, files_to_move: Vec<MoveFiles>) -> Self {
+        let swaps = vec![None; files_to_move.len()];
+        let checksums = files_to_move.iter().map(|_| OnceLock::new()).collect();
+        let deleted = vec![false; files_to_move.len()];
         FilesMover {
             config,
             files_to_move,
+            swaps,
+            checksums,
+            duplicates: Vec::new(),
+            deleted,
         }
     }
 
+    /// Function sets pairs whose target is a duplicate of the source (`Resolution::duplicates`):
+    /// their sources are deleted or replaced with hard links to the targets after the batch
+    /// is moved, according to `Config::on_duplicate`.
+    pub fn with_duplicates(mut self, duplicates: Vec<MoveFiles>) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// Function sets files to delete in the batch (`--edit` with `--allow-delete`).
+    /// A deleted file is moved to `.name.mmv-delete` with other moves, so its name may be
+    /// a target of another move, and it is removed after the batch is moved.
+    pub fn with_deletions(mut self, files_to_delete: Vec<PathBuf>) -> Self {
+        for file in files_to_delete {
+            let temporary = temporary_path(&file, "delete");
+            self.files_to_move.push(MoveFiles::new(file, temporary));
+            self.swaps.push(None);
+            self.checksums.push(OnceLock::new());
+            self.deleted.push(true);
+        }
+        self
+    }
+
+    /// Function returns the pairs of files to move, without files to delete.
+    pub fn files_to_move(&self) -> &[MoveFiles] {
+        let moves = self.deleted.iter().take_while(|&&deleted| !deleted).count();
+        &self.files_to_move[..moves]
+    }
+
     /// Function that checks if the target path is valid.
-    /// Usage: `correct_target_path("path/to/file")?`
-    fn correct_target_path(&self, target_path: &str) -> Result<(), MassMoveError> {
-        let path = PathBuf::from(target_path);
+    /// Usage: `correct_target_path(Path::new("path/to/file"))?`
+    fn correct_target_path(&self, path: &Path) -> Result<(), MassMoveError> {
         let parent = path.parent();
 
         if parent.is_none() {
-            return Err(MassMoveError::InvalidTargetPath(target_path.to_owned()));
+            return Err(MassMoveError::InvalidTargetPath(path.display().to_string()));
         }
 
-        let is_empty_parent = parent.unwrap().to_str().unwrap().is_empty();
-        if !is_empty_parent && !parent.unwrap().exists() {
+        let is_empty_parent = parent.unwrap().as_os_str().is_empty();
+        if !is_empty_parent
+            && !self.config.create_dirs()
+            && !self.config.file_system().exists(parent.unwrap())
+        {
             return Err(MassMoveError::DirectoryNotFound(
-                parent.unwrap().to_str().unwrap().to_owned(),
+                parent.unwrap().display().to_string(),
             ));
         }
 
-        if !self.config.force_move() && path.exists() {
-            return Err(MassMoveError::FileAlreadyExists(String::from(target_path)));
+        if !self.config.force_move() && self.config.file_system().exists(path) {
+            return Err(MassMoveError::FileAlreadyExists(path.display().to_string()));
         }
 
         Ok(())
     }
 
+    /// Function validates the whole batch before any file is moved.
+    /// All found problems are aggregated: files moved to the same target, files moved
+    /// several times and targets that can not be created.
+    pub(crate) fn validate(&self) -> Result<(), MassMoveError> {
+        MassMoveError::from_errors(
+            self.find_conflicts()
+                .into_iter()
+                .map(|(_, err)| err)
+                .collect(),
+        )
+    }
+
+    /// Function finds problems of the batch, every problem is returned with indices
+    /// of the moves it is caused by.
+    fn find_conflicts(&self) -> Vec<(Vec<usize>, MassMoveError)> {
+        let mut conflicts = Vec::new();
+
+        let mut sources: HashMap<&Path, Vec<usize>> = HashMap::new();
+        let mut targets: HashMap<&Path, Vec<usize>> = HashMap::new();
+        for (i, file_pair) in self.files_to_move.iter().enumerate() {
+            sources.entry(&file_pair.from).or_default().push(i);
+            targets.entry(&file_pair.to).or_default().push(i);
+        }
+
+        let mut reported: HashSet<&Path> = HashSet::new();
+        for (i, file_pair) in self.files_to_move.iter().enumerate() {
+            let same_sources = &sources[file_pair.from.as_path()];
+            if same_sources.len() > 1 && reported.insert(file_pair.from.as_path()) {
+                conflicts.push((
+                    same_sources.clone(),
+                    MassMoveError::DuplicateSource(file_pair.from.display().to_string()),
+                ));
+            }
+
+            let target_sources = &targets[file_pair.to.as_path()];
+            if target_sources.len() > 1 {
+                if target_sources[0] == i {
+                    let paths: Vec<String> = target_sources
+                        .iter()
+                        .map(|&j| self.files_to_move[j].from.display().to_string())
+                        .collect();
+                    conflicts.push((
+                        target_sources.clone(),
+                        MassMoveError::TargetCollision(format!(
+                            "{} <- {}",
+                            file_pair.to.display(),
+                            paths.join(", ")
+                        )),
+                    ));
+                }
+                continue;
+            }
+
+            let is_moved_away =
+                file_pair.from != file_pair.to && sources.contains_key(file_pair.to.as_path());
+            if !is_moved_away {
+                if let Err(err) = self.correct_target_path(&file_pair.to) {
+                    conflicts.push((vec![i], err));
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Function returns indices of moves in the order they have to be done.
+    /// A move to a path that is a source of another move is done after that move.
+    /// Moves that do not depend on each other keep the order of the plan.
+    /// Returns `MassMoveError::MoveCycle` if moves form a cycle (e.g. `a -> b`, `b -> a`).
+    pub(crate) fn schedule(&self) -> Result<Vec<usize>, MassMoveError> {
+        let excluded = vec![false; self.files_to_move.len()];
+        self.schedule_moves(&excluded).map_err(|(_, err)| err)
+    }
+
+    /// Function schedules moves that are not excluded, see `schedule`.
+    /// A cycle is returned with indices of the moves in it.
+    fn schedule_moves(&self, excluded: &[bool]) -> Result<Vec<usize>, (Vec<usize>, MassMoveError)> {
+        let sources: HashMap<&Path, usize> = self
+            .files_to_move
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !excluded[i])
+            .map(|(i, file_pair)| (file_pair.from.as_path(), i))
+            .collect();
+
+        // blocked_by[i] - a move that has to be done before the move i.
+        // A swap is done by its first move, so it is not blocked and the second move is skipped.
+        let swaps = self.find_swaps(excluded);
+        let blocked_by: Vec<Option<usize>> = self
+            .files_to_move
+            .iter()
+            .enumerate()
+            .map(|(i, file_pair)| {
+                sources
+                    .get(file_pair.to.as_path())
+                    .copied()
+                    .filter(|&j| j != i && swaps[i] != Some(j))
+            })
+            .collect();
+
+        let mut order = Vec::with_capacity(self.files_to_move.len());
+        let mut is_scheduled = excluded.to_vec();
+        for (i, swap) in swaps.iter().enumerate() {
+            if swap.is_some_and(|j| j < i) {
+                is_scheduled[i] = true;
+            }
+        }
+        let mut is_in_chain = vec![false; self.files_to_move.len()];
+        for start in 0..self.files_to_move.len() {
+            // Walk the chain from `start` to its end and schedule it backwards.
+            let mut chain = Vec::new();
+            let mut current = Some(start);
+            while let Some(i) = current {
+                if is_scheduled[i] {
+                    break;
+                }
+                if is_in_chain[i] {
+                    let cycle_start = chain.iter().position(|&j| j == i).unwrap();
+                    let cycle = chain[cycle_start..].to_vec();
+                    let paths: Vec<String> = cycle
+                        .iter()
+                        .chain([&i])
+                        .map(|&j| self.files_to_move[j].from.display().to_string())
+                        .collect();
+                    return Err((cycle, MassMoveError::MoveCycle(paths.join(" -> "))));
+                }
+                is_in_chain[i] = true;
+                chain.push(i);
+                current = blocked_by[i];
+            }
+
+            for &i in chain.iter().rev() {
+                is_scheduled[i] = true;
+                order.push(i);
+            }
+        }
+
+        Ok(order)
+    }
+
+    /// Function finds swaps: pairs of moves that are not excluded and move two files
+    /// to each other's names. Both moves of a swap point to each other.
+    fn find_swaps(&self, excluded: &[bool]) -> Vec<Option<usize>> {
+        let sources: HashMap<&Path, usize> = self
+            .files_to_move
+            .iter()
+            .enumerate()
+            .filter(|&(i, file_pair)| !excluded[i] && file_pair.from != file_pair.to)
+            .map(|(i, file_pair)| (file_pair.from.as_path(), i))
+            .collect();
+        self.files_to_move
+            .iter()
+            .enumerate()
+            .map(|(i, file_pair)| {
+                let &j = sources.get(file_pair.to.as_path())?;
+                let is_swap = !excluded[i]
+                    && i != j
+                    && sources.get(file_pair.from.as_path()) == Some(&i)
+                    && self.files_to_move[j].to == file_pair.from;
+                is_swap.then_some(j)
+            })
+            .collect()
+    }
+
     /// Function that move a concrete file from one location to another.
-    fn move_file(&self, from: String, to: String) -> Result<(), MassMoveError> {
-        self.correct_target_path(&to)?;
+    /// A swap is done by its first move, the target of a swap exists.
+    fn move_file(&self, i: usize, progress: &Progress) -> Result<(), MassMoveError> {
+        let MoveFiles { from, to } = &self.files_to_move[i];
+        let result = if self.swaps[i].is_some() {
+            self.exchange(from, to)
+        } else {
+            self.correct_target_path(to)?;
+            self.create_parent(to)?;
+            self.rename_or_copy(from, to, self.config.force_move(), progress)
+                .map(|checksum| {
+                    if let Some(checksum) = checksum {
+                        let _ = self.checksums[i].set(checksum);
+                    }
+                })
+        };
+
+        result.map_err(|source| match source.kind() {
+            _ if self.deleted[i] => MassMoveError::Io {
+                operation: Operation::DeleteFile,
+                path: from.to_owned(),
+                source,
+            },
+            io::ErrorKind::AlreadyExists => {
+                MassMoveError::FileAlreadyExists(to.display().to_string())
+            }
+            _ => MassMoveError::MoveError {
+                from: from.to_owned(),
+                to: to.to_owned(),
+                source,
+            },
+        })
+    }
 
-        match fs::rename(&from, &to) {
-            Ok(_) => {
-                println!("{} -> {}", from, to);
-                Ok(())
+    /// Function creates missing parent directories of the target with `--create-dirs`.
+    fn create_parent(&self, path: &Path) -> Result<(), MassMoveError> {
+        let file_system = self.config.file_system();
+        match path.parent() {
+            Some(parent)
+                if self.config.create_dirs()
+                    && !parent.as_os_str().is_empty()
+                    && !file_system.exists(parent) =>
+            {
+                file_system
+                    .create_dir_all(parent)
+                    .map_err(|source| MassMoveError::Io {
+                        operation: Operation::CreateDirectory,
+                        path: parent.to_owned(),
+                        source,
+                    })
             }
-            Err(_) => Err(MassMoveError::MoveError(String::from(&from))),
+            _ => Ok(()),
         }
     }
 
-    /// Function that moves all files from the vector of MoveFiles.
-    /// It drops the struct after moving files.
-    pub fn run(self) -> Result<(), MassMoveError> {
+    /// Function renames a file, an existing target is replaced only if `overwrite`.
+    /// A file can not be renamed to another file system, so it is copied to a temporary file
+    /// next to the target, the copy is renamed to the target and the source is removed then.
+    /// Copied bytes are counted by the progress. If the source can not be removed,
+    /// the copy is removed and the source is kept.
+    /// With `--verify` checksums of the source and the copy are compared before the source
+    /// is removed, the checksum of a verified copy is returned.
+    fn rename_or_copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        overwrite: bool,
+        progress: &Progress,
+    ) -> io::Result<Option<String>> {
+        let file_system = self.config.file_system();
+        let rename = |from: &Path, to: &Path| {
+            if overwrite {
+                file_system.rename(from, to)
+            } else {
+                file_system.rename_noreplace(from, to)
+            }
+        };
+        match rename(from, to) {
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                let checksum = if self.config.verify() {
+                    Some(hash_file(file_system.as_ref(), from, VERIFY_ALGORITHM)?)
+                } else {
+                    None
+                };
+                let part = temporary_path(to, "part");
+                file_system.copy_file(from, &part, &mut |bytes| progress.copied(bytes))?;
+                if let Some(checksum) = &checksum {
+                    let verified = hash_file(file_system.as_ref(), &part, VERIFY_ALGORITHM)
+                        .and_then(|copied| {
+                            if &copied == checksum {
+                                Ok(())
+                            } else {
+                                Err(io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    format!("checksum mismatch: {} != {}", copied, checksum),
+                                ))
+                            }
+                        });
+                    verified.inspect_err(|_| {
+                        let _ = file_system.remove_file(&part);
+                    })?;
+                }
+                rename(&part, to).inspect_err(|_| {
+                    let _ = file_system.remove_file(&part);
+                })?;
+                file_system.remove_file(from).inspect_err(|_| {
+                    let _ = file_system.remove_file(to);
+                })?;
+                Ok(checksum.map(|checksum| format!("{}:{}", VERIFY_ALGORITHM.name(), checksum)))
+            }
+            result => result.map(|_| None),
+        }
+    }
+
+    /// Function removes temporary files of deleted files after the batch is moved,
+    /// `done` are indices of the done moves. Failures are reported and collected in `errors`.
+    fn remove_deleted(
+        &self,
+        done: impl IntoIterator<Item = usize>,
+        reporter: &Reporter,
+        errors: &mut Vec<MassMoveError>,
+    ) {
+        for i in done.into_iter().filter(|&i| self.deleted[i]) {
+            let MoveFiles { from, to } = &self.files_to_move[i];
+            match self.config.file_system().remove_file(to) {
+                Ok(()) => reporter.report(from, None, MoveStatus::Deleted, None),
+                Err(source) => {
+                    let err = MassMoveError::Io {
+                        operation: Operation::DeleteFile,
+                        path: to.clone(),
+                        source,
+                    };
+                    reporter.report(from, None, MoveStatus::Failed, Some(&err));
+                    errors.push(err);
+                }
+            }
+        }
+    }
+
+    /// Function deletes sources of duplicates or replaces them with hard links to the targets.
+    /// Failures are reported and collected in `errors`, other duplicates are processed.
+    fn apply_duplicates(&self, reporter: &Reporter, errors: &mut Vec<MassMoveError>) {
+        let file_system = self.config.file_system();
+        for MoveFiles { from, to } in &self.duplicates {
+            if self.config.dry_run() {
+                let status = match self.config.on_duplicate() {
+                    Some(OnDuplicate::Link) => MoveStatus::PlannedLink,
+                    _ => MoveStatus::PlannedDelete,
+                };
+                reporter.report(from, Some(to), status, None);
+                continue;
+            }
+            let (status, operation, result) = match self.config.on_duplicate() {
+                Some(OnDuplicate::Link) => (MoveStatus::Linked, Operation::LinkFile, {
+                    let link = temporary_path(from, "link");
+                    file_system.hard_link(to, &link).and_then(|_| {
+                        file_system.rename(&link, from).inspect_err(|_| {
+                            let _ = file_system.remove_file(&link);
+                        })
+                    })
+                }),
+                Some(OnDuplicate::DeleteSource) => (
+                    MoveStatus::Deleted,
+                    Operation::DeleteFile,
+                    file_system.remove_file(from),
+                ),
+                _ => unreachable!("duplicates are checked by check_duplicates"),
+            };
+            match result {
+                Ok(()) => reporter.report(from, Some(to), status, None),
+                Err(source) => {
+                    let err = MassMoveError::Io {
+                        operation,
+                        path: from.clone(),
+                        source,
+                    };
+                    reporter.report(from, Some(to), MoveStatus::Failed, Some(&err));
+                    errors.push(err);
+                }
+            }
+        }
+    }
+
+    /// Function checks that duplicates are processed by a policy that deletes or links them,
+    /// so a source is never deleted without `--on-duplicate delete-source`.
+    fn check_duplicates(&self) -> Result<(), MassMoveError> {
+        match self.config.on_duplicate() {
+            _ if self.duplicates.is_empty() => Ok(()),
+            Some(OnDuplicate::DeleteSource | OnDuplicate::Link) => Ok(()),
+            _ => Err(MassMoveError::InvalidArguments(format!(
+                "{} is a duplicate of {}, it requires --on-duplicate delete-source or link",
+                self.duplicates[0].from.display(),
+                self.duplicates[0].to.display()
+            ))),
+        }
+    }
+
+    /// Function swaps two files. If the file system can not swap them atomically,
+    /// they are swapped with three renames through a temporary file.
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()> {
+        let file_system = self.config.file_system();
+        match file_system.exchange(first, second) {
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                let temporary = temporary_path(first, "swap");
+                file_system.rename_noreplace(first, &temporary)?;
+                if let Err(err) = file_system.rename_noreplace(second, first) {
+                    let _ = file_system.rename_noreplace(&temporary, first);
+                    return Err(err);
+                }
+                file_system
+                    .rename_noreplace(&temporary, second)
+                    .inspect_err(|_| {
+                        let _ = file_system.rename_noreplace(first, second);
+                        let _ = file_system.rename_noreplace(&temporary, first);
+                    })
+            }
+            result => result,
+        }
+    }
+
+    /// Function reports a pair of files with a status.
+    fn report(
+        &self,
+        reporter: &Reporter,
+        i: usize,
+        status: MoveStatus,
+        error: Option<&MassMoveError>,
+    ) {
+        if self.deleted[i] {
+            // A deleted file is reported when its temporary file is removed.
+            if !matches!(status, MoveStatus::Moved | MoveStatus::RolledBack) {
+                reporter.report(&self.files_to_move[i].from, None, status, error);
+            }
+            return;
+        }
+        for i in [Some(i), self.swaps[i]].into_iter().flatten() {
+            let file_pair = &self.files_to_move[i];
+            let checksum = match status {
+                MoveStatus::Moved => self.checksums[i].get().map(String::as_str),
+                _ => None,
+            };
+            reporter.report_with_checksum(
+                &file_pair.from,
+                Some(&file_pair.to),
+                status,
+                error,
+                checksum,
+            );
+        }
+    }
+
+    /// Function rolls back done moves (the journal) in the reverse order.
+    /// It returns the original error, with rollback failures if there are any.
+    fn rollback(
+        &self,
+        journal: &[usize],
+        err: MassMoveError,
+        reporter: &Reporter,
+    ) -> MassMoveError {
+        let mut errors = vec![err];
+        for &i in journal.iter().rev() {
+            let file_pair = &self.files_to_move[i];
+            let result = if self.swaps[i].is_some() {
+                self.exchange(&file_pair.to, &file_pair.from)
+            } else {
+                self.rename_or_copy(&file_pair.to, &file_pair.from, false, reporter.progress())
+                    .map(|_| ())
+            };
+            if let Err(source) = result {
+                let err = MassMoveError::RollbackError {
+                    from: file_pair.to.clone(),
+                    to: file_pair.from.clone(),
+                    source,
+                };
+                self.report(reporter, i, MoveStatus::Failed, Some(&err));
+                errors.push(err);
+            } else {
+                self.report(reporter, i, MoveStatus::RolledBack, None);
+            }
+        }
+
+        let err = MassMoveError::from_errors(errors).unwrap_err();
+        let rolled_back: usize = journal
+            .iter()
+            .filter(|&&i| !self.deleted[i])
+            .map(|&i| 1 + usize::from(self.swaps[i].is_some()))
+            .sum();
+        if rolled_back == 0 {
+            err
+        } else {
+            MassMoveError::RolledBack(Box::new(err), rolled_back)
+        }
+    }
+
+    /// Function marks the moves that may be done in any order.
+    /// A move is dependent (chained) if its target is a source of another move,
+    /// its source is a target of another move, or its target is shared with another move.
+    fn independent_moves(&self) -> Vec<bool> {
+        let mut sources: HashMap<&Path, usize> = HashMap::new();
+        let mut targets: HashMap<&Path, usize> = HashMap::new();
         for file_pair in &self.files_to_move {
-            self.move_file(file_pair.from.to_owned(), file_pair.to.to_owned())?;
+            *sources.entry(&file_pair.from).or_default() += 1;
+            *targets.entry(&file_pair.to).or_default() += 1;
+        }
+
+        self.files_to_move
+            .iter()
+            .map(|file_pair| {
+                let is_self_move = file_pair.from == file_pair.to;
+                let to_is_source = sources.get(file_pair.to.as_path()).copied().unwrap_or(0)
+                    > usize::from(is_self_move);
+                let from_is_target = targets.get(file_pair.from.as_path()).copied().unwrap_or(0)
+                    > usize::from(is_self_move);
+                let shared_target = targets[file_pair.to.as_path()] > 1;
+                !(to_is_source || from_is_target || shared_target)
+            })
+            .collect()
+    }
+
+    /// Function moves files on the rayon thread pool.
+    /// Independent moves are done in parallel, results are printed in the order of the plan
+    /// and all errors are aggregated. Dependent moves are done sequentially only if the
+    /// parallel part succeeded.
+    fn run_parallel(&self, order: Vec<usize>, reporter: &Reporter) -> Result<(), MassMoveError> {
+        let independent = self.independent_moves();
+        let results: Vec<Option<Result<(), MassMoveError>>> = independent
+            .par_iter()
+            .enumerate()
+            .map(|(i, &is_independent)| {
+                is_independent.then(|| self.move_file(i, reporter.progress()))
+            })
+            .collect();
+
+        let mut journal = Vec::new();
+        let mut errors = Vec::new();
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Some(Ok(())) => {
+                    self.report(reporter, i, MoveStatus::Moved, None);
+                    journal.push(i);
+                }
+                Some(Err(err)) => {
+                    self.report(reporter, i, MoveStatus::Failed, Some(&err));
+                    errors.push(err);
+                }
+                None => {}
+            }
+        }
+        let dependent: Vec<usize> = order.into_iter().filter(|&i| !independent[i]).collect();
+        if let Err(err) = MassMoveError::from_errors(errors) {
+            self.report_skipped(&dependent, reporter);
+            return Err(self.rollback(&journal, err, reporter));
+        }
+
+        self.run_sequential(&dependent, journal, reporter)
+    }
+
+    /// Function moves files one by one in the order, `journal` holds moves that are already done.
+    fn run_sequential(
+        &self,
+        order: &[usize],
+        mut journal: Vec<usize>,
+        reporter: &Reporter,
+    ) -> Result<(), MassMoveError> {
+        for (position, &i) in order.iter().enumerate() {
+            if let Err(err) = self.move_file(i, reporter.progress()) {
+                self.report(reporter, i, MoveStatus::Failed, Some(&err));
+                self.report_skipped(&order[position + 1..], reporter);
+                return Err(self.rollback(&journal, err, reporter));
+            }
+            journal.push(i);
+            self.report(reporter, i, MoveStatus::Moved, None);
         }
         Ok(())
     }
+
+    /// Function reports moves that are not done because the batch is aborted.
+    fn report_skipped(&self, order: &[usize], reporter: &Reporter) {
+        for &i in order {
+            self.report(reporter, i, MoveStatus::Skipped, None);
+        }
+    }
+
+    /// Function reports duplicates that are not processed because the batch is aborted.
+    fn report_skipped_duplicates(&self, reporter: &Reporter) {
+        for MoveFiles { from, to } in &self.duplicates {
+            reporter.report(from, Some(to), MoveStatus::Skipped, None);
+        }
+    }
+
+    /// Function moves files in `--keep-going` mode: moves with problems (collisions, cycles,
+    /// existing targets) are reported as failed and excluded from the batch, failed moves are
+    /// not rolled back, all other files are moved. All errors are aggregated.
+    /// Sources of failed moves stay in place, so moves to them are skipped, as are moves
+    /// to sources of skipped moves; otherwise a forced move would replace them.
+    fn run_keep_going(mut self, reporter: &Reporter) -> Result<(), MassMoveError> {
+        let mut errors = Vec::new();
+        let mut excluded = vec![false; self.files_to_move.len()];
+        let mut exclude = |indices: Vec<usize>, err: MassMoveError, excluded: &mut [bool]| {
+            for i in indices {
+                if !excluded[i] {
+                    excluded[i] = true;
+                    self.report(reporter, i, MoveStatus::Failed, Some(&err));
+                }
+            }
+            errors.push(err);
+        };
+
+        for (indices, err) in self.find_conflicts() {
+            exclude(indices, err, &mut excluded);
+        }
+        let order = loop {
+            match self.schedule_moves(&excluded) {
+                Ok(order) => break order,
+                Err((cycle, err)) => exclude(cycle, err, &mut excluded),
+            }
+        };
+        self.swaps = self.find_swaps(&excluded);
+
+        if self.config.dry_run() {
+            for i in order {
+                self.report(reporter, i, MoveStatus::Planned, None);
+            }
+            self.apply_duplicates(reporter, &mut errors);
+            return MassMoveError::from_errors(errors);
+        }
+
+        let independent = if self.config.is_parallel() {
+            self.independent_moves()
+        } else {
+            vec![false; self.files_to_move.len()]
+        };
+        let parallel_results: Vec<(usize, Result<(), MassMoveError>)> = order
+            .par_iter()
+            .filter(|&&i| independent[i])
+            .map(|&i| (i, self.move_file(i, reporter.progress())))
+            .collect();
+        let sequential = order
+            .iter()
+            .filter(|&&i| !independent[i])
+            .map(|&i| (i, None));
+
+        let mut done = Vec::new();
+        let mut kept: HashSet<&Path> = HashSet::new();
+        for (i, result) in parallel_results
+            .into_iter()
+            .map(|(i, result)| (i, Some(result)))
+            .chain(sequential)
+        {
+            let MoveFiles { from, to } = &self.files_to_move[i];
+            let result = match result {
+                Some(result) => result,
+                None if kept.contains(to.as_path()) => {
+                    self.report(reporter, i, MoveStatus::Skipped, None);
+                    kept.insert(from);
+                    continue;
+                }
+                None => self.move_file(i, reporter.progress()),
+            };
+            match result {
+                Ok(()) => {
+                    self.report(reporter, i, MoveStatus::Moved, None);
+                    done.push(i);
+                }
+                Err(err) => {
+                    self.report(reporter, i, MoveStatus::Failed, Some(&err));
+                    kept.insert(from);
+                    errors.push(err);
+                }
+            }
+        }
+        self.remove_deleted(done, reporter, &mut errors);
+        self.apply_duplicates(reporter, &mut errors);
+        MassMoveError::from_errors(errors)
+    }
+
+    /// Function that moves all files from the vector of MoveFiles.
+    /// It drops the struct after moving files.
+    /// If the batch is invalid, no file is moved and all pairs are reported as skipped
+    /// (unless `--keep-going`, see `run_keep_going`).
+    /// With `Config::with_jobs` files are moved on a thread pool of that many threads.
+    pub fn run(self, reporter: &Reporter) -> Result<(), MassMoveError> {
+        match thread_pool(&self.config)? {
+            Some(pool) => pool.install(|| self.run_batch(reporter)),
+            None => self.run_batch(reporter),
+        }
+    }
+
+    /// Function moves the batch on the current thread pool, see `run`.
+    fn run_batch(mut self, reporter: &Reporter) -> Result<(), MassMoveError> {
+        reporter.align(
+            self.files_to_move
+                .iter()
+                .map(|file_pair| file_pair.from.as_path()),
+        );
+        if !self.config.dry_run() {
+            reporter.progress().set_total(self.files_to_move().len());
+        }
+        if let Err(err) = self.check_duplicates() {
+            let all: Vec<usize> = (0..self.files_to_move.len()).collect();
+            self.report_skipped(&all, reporter);
+            self.report_skipped_duplicates(reporter);
+            return Err(err);
+        }
+        if self.config.keep_going() {
+            return self.run_keep_going(reporter);
+        }
+
+        let order = match self.validate().and_then(|_| self.schedule()) {
+            Ok(order) => order,
+            Err(err) => {
+                let all: Vec<usize> = (0..self.files_to_move.len()).collect();
+                self.report_skipped(&all, reporter);
+                self.report_skipped_duplicates(reporter);
+                return Err(err);
+            }
+        };
+        self.swaps = self.find_swaps(&vec![false; self.files_to_move.len()]);
+
+        let mut errors = Vec::new();
+        if !self.config.dry_run() {
+            let result = if self.config.is_parallel() {
+                self.run_parallel(order, reporter)
+            } else {
+                self.run_sequential(&order, Vec::new(), reporter)
+            };
+            if let Err(err) = result {
+                self.report_skipped_duplicates(reporter);
+                return Err(err);
+            }
+            self.remove_deleted(0..self.files_to_move.len(), reporter, &mut errors);
+        } else {
+            for i in order {
+                self.report(reporter, i, MoveStatus::Planned, None);
+            }
+        }
+
+        self.apply_duplicates(reporter, &mut errors);
+        MassMoveError::from_errors(errors)
+    }
+}
+
+/// Function builds a thread pool of `Config::jobs` threads to match and move files on.
+/// It returns `None` if files are matched and moved sequentially.
+pub(crate) fn thread_pool(config: &Config) -> Result<Option<ThreadPool>, MassMoveError> {
+    if !config.is_parallel() {
+        return Ok(None);
+    }
+    ThreadPoolBuilder::new()
+        .num_threads(config.jobs())
+        .build()
+        .map(Some)
+        .map_err(|err| MassMoveError::Error(io::Error::other(err)))
+}
+
+/// Function returns a hidden temporary path next to the file: `dir/.name.mmv-part`.
+fn temporary_path(path: &Path, kind: &str) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".mmv-{}", kind));
+    path.with_file_name(file_name)
+}
+
+/// Function builds moves from pairs of paths, it is used in tests.
+#[cfg(test)]
+fn moves(pairs: &[(&str, &str)]) -> Vec<MoveFiles> {
+    pairs
+        .iter()
+        .map(|(from, to)| MoveFiles {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        })
+        .collect()
+}
+
+#[test]
+fn test_independent_moves() {
+    type Case<'a> = (Vec<(&'a str, &'a str)>, Vec<bool>);
+    let cases: Vec<Case> = vec![
+        (vec![("a", "b"), ("c", "d")], vec![true, true]),
+        (vec![("a", "b"), ("b", "c")], vec![false, false]),
+        (vec![("a", "b"), ("b", "a")], vec![false, false]),
+        (
+            vec![("a", "x"), ("b", "x"), ("c", "d")],
+            vec![false, false, true],
+        ),
+        (vec![("a", "a"), ("b", "c")], vec![true, true]),
+    ];
+
+    for case in cases {
+        let mover = FilesMover::new(Config::new(), moves(&case.0));
+        assert_eq!(mover.independent_moves(), case.1, "moves: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_schedule() {
+    type Case<'a> = (Vec<(&'a str, &'a str)>, Option<Vec<usize>>);
+    let cases: Vec<Case> = vec![
+        (vec![("a", "b"), ("c", "d")], Some(vec![0, 1])),
+        (vec![("a", "b"), ("b", "c")], Some(vec![1, 0])),
+        (
+            vec![("a", "b"), ("b", "c"), ("c", "d")],
+            Some(vec![2, 1, 0]),
+        ),
+        (
+            vec![("c", "d"), ("a", "b"), ("b", "c")],
+            Some(vec![0, 2, 1]),
+        ),
+        (vec![("a", "a")], Some(vec![0])),
+        (vec![("a", "b"), ("b", "a")], Some(vec![0])),
+        (vec![("b", "a"), ("x", "y"), ("a", "b")], Some(vec![0, 1])),
+        (vec![("x", "y"), ("a", "b"), ("b", "c"), ("c", "a")], None),
+    ];
+
+    for case in cases {
+        let mover = FilesMover::new(Config::new(), moves(&case.0));
+        assert_eq!(mover.schedule().ok(), case.1, "moves: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_schedule_excluded_cycle() {
+    let mover = FilesMover::new(
+        Config::new(),
+        moves(&[("x", "y"), ("a", "b"), ("b", "c"), ("c", "a"), ("d", "x")]),
+    );
+    let mut excluded = vec![false; 5];
+
+    let (cycle, _) = mover.schedule_moves(&excluded).unwrap_err();
+    assert_eq!(cycle, vec![1, 2, 3]);
+
+    for i in cycle {
+        excluded[i] = true;
+    }
+    assert_eq!(mover.schedule_moves(&excluded).ok(), Some(vec![0, 4]));
+}
+
+#[test]
+fn test_validate_collisions() {
+    type Case<'a> = (Vec<(&'a str, &'a str)>, usize);
+    let cases: Vec<Case> = vec![
+        (vec![("a", "x"), ("b", "x")], 1),
+        (vec![("a", "x"), ("b", "x"), ("c", "y"), ("d", "y")], 2),
+        (vec![("a", "x"), ("a", "y")], 1),
+    ];
+
+    for case in cases {
+        let mover = FilesMover::new(Config::new(), moves(&case.0));
+        let errors = match mover.validate() {
+            Err(MassMoveError::Multiple(errors)) => errors.len(),
+            Err(_) => 1,
+            Ok(_) => 0,
+        };
+        assert_eq!(errors, case.1, "moves: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_run_on_memory_file_system() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (Vec<(&'a str, &'a str)>, bool, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            vec![("a", "b"), ("c", "d")],
+            true,
+            vec!["b", "d", "dir/x", "x"],
+        ),
+        (
+            vec![("x", "y"), ("a", "x")],
+            true,
+            vec!["c", "dir/x", "x", "y"],
+        ),
+        (vec![("a", "c")], false, vec!["a", "c", "dir/x", "x"]),
+        (
+            vec![("a", "b"), ("c", "b")],
+            false,
+            vec!["a", "c", "dir/x", "x"],
+        ),
+        (
+            vec![("a", "missing/a")],
+            false,
+            vec!["a", "c", "dir/x", "x"],
+        ),
+        (
+            vec![("a", "c"), ("c", "x"), ("x", "a")],
+            false,
+            vec!["a", "c", "dir/x", "x"],
+        ),
+        (
+            vec![("c", "d"), ("a", "locked/a")],
+            false,
+            vec!["a", "c", "dir/x", "x"],
+        ),
+        (
+            vec![("dir/x", "dir/y"), ("a", "b")],
+            false,
+            vec!["a", "c", "dir/x", "x"],
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "a");
+        fs.add_file("c", "c");
+        fs.add_file("x", "x");
+        fs.add_file("dir/x", "x");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+        fs.set_mode("dir", 0o555);
+
+        let config = Config::new().with_file_system(fs.clone());
+        let result = FilesMover::new(config, moves(&case.0)).run(&Reporter::silent(false));
+        assert_eq!(result.is_ok(), case.1, "moves: {:?}", case.0);
+        let expected: Vec<PathBuf> = case.2.iter().map(PathBuf::from).collect();
+        assert_eq!(fs.files(), expected, "moves: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_run_on_thread_pool() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    let cases: Vec<(usize, Option<usize>)> = vec![(1, None), (2, Some(2)), (4, Some(4))];
+
+    // Batches with different numbers of jobs are moved in one process.
+    for case in cases {
+        let config = Config::new().with_jobs(case.0);
+        let threads = thread_pool(&config)
+            .unwrap()
+            .map(|pool| pool.current_num_threads());
+        assert_eq!(threads, case.1, "jobs: {}", case.0);
+
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "a");
+        fs.add_file("c", "c");
+        let config = config.with_file_system(fs.clone());
+        FilesMover::new(config, moves(&[("a", "b"), ("c", "d")]))
+            .run(&Reporter::silent(false))
+            .unwrap();
+        assert_eq!(
+            fs.files(),
+            vec![PathBuf::from("b"), PathBuf::from("d")],
+            "jobs: {}",
+            case.0
+        );
+    }
+}
+
+#[test]
+fn test_run_deletions() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (Vec<(&'a str, &'a str)>, bool, bool, usize, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (vec![("c", "x")], false, true, 1, vec!["a", "x"]),
+        (
+            vec![("c", "d"), ("a", "locked/a")],
+            false,
+            false,
+            0,
+            vec!["a", "c", "x"],
+        ),
+        (
+            vec![("c", "d"), ("a", "locked/a")],
+            true,
+            false,
+            1,
+            vec!["a", "d"],
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "a");
+        fs.add_file("c", "c");
+        fs.add_file("x", "x");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_keep_going(case.1);
+        let reporter = Reporter::silent(false);
+        let result = FilesMover::new(config, moves(&case.0))
+            .with_deletions(vec![PathBuf::from("x")])
+            .run(&reporter);
+        assert_eq!(result.is_ok(), case.2, "moves: {:?}", case.0);
+        assert_eq!(
+            reporter.count(MoveStatus::Deleted),
+            case.3,
+            "moves: {:?}",
+            case.0
+        );
+        let expected: Vec<PathBuf> = case.4.iter().map(PathBuf::from).collect();
+        assert_eq!(fs.files(), expected, "moves: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_run_keep_going_failed_chain() {
+    use super::file_system::{FileSystem, MemoryFileSystem};
+    use std::io::Read;
+    use std::sync::Arc;
+
+    for jobs in [1, 4] {
+        let fs = Arc::new(MemoryFileSystem::new());
+        for file in ["a", "b", "d", "z"] {
+            fs.add_file(file, file);
+        }
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_force_move(true)
+            .with_keep_going(true)
+            .with_jobs(jobs);
+        let reporter = Reporter::silent(false);
+        let result = FilesMover::new(
+            config,
+            moves(&[("z", "a"), ("a", "b"), ("b", "locked/b"), ("d", "e")]),
+        )
+        .run(&reporter);
+        assert!(result.is_err(), "jobs: {}", jobs);
+        let counts = [MoveStatus::Moved, MoveStatus::Skipped, MoveStatus::Failed]
+            .map(|status| reporter.count(status));
+        assert_eq!(counts, [1, 2, 1], "jobs: {}", jobs);
+        let expected: Vec<PathBuf> = ["a", "b", "e", "z"].iter().map(PathBuf::from).collect();
+        assert_eq!(fs.files(), expected, "jobs: {}", jobs);
+        for file in ["a", "b", "z"] {
+            let mut content = String::new();
+            fs.open(Path::new(file))
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, file, "jobs: {}", jobs);
+        }
+    }
+}
+
+#[test]
+fn test_run_across_file_systems() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (Vec<(&'a str, &'a str)>, bool, Vec<&'a str>, &'a str);
+    let cases: Vec<Case> = vec![
+        (
+            vec![("a", "mnt/a"), ("c", "mnt/d")],
+            true,
+            vec!["mnt/a", "mnt/d"],
+            "moved 2/2, copied 5 B",
+        ),
+        (
+            vec![("a", "mnt/a"), ("c", "locked/c")],
+            false,
+            vec!["a", "c"],
+            "moved 2/2, copied 6 B",
+        ),
+        (vec![("a", "b")], true, vec!["b", "c"], "moved 1/1"),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "aaa");
+        fs.add_file("c", "cc");
+        fs.add_mount_point("mnt");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let config = Config::new().with_file_system(fs.clone());
+        let reporter = Reporter::silent(false);
+        let result = FilesMover::new(config, moves(&case.0)).run(&reporter);
+        assert_eq!(result.is_ok(), case.1, "moves: {:?}", case.0);
+        let expected: Vec<PathBuf> = case.2.iter().map(PathBuf::from).collect();
+        assert_eq!(fs.files(), expected, "moves: {:?}", case.0);
+        assert_eq!(
+            reporter.progress().status_line(),
+            format!("scanned 0, matched 0, {}", case.3),
+            "moves: {:?}",
+            case.0
+        );
+    }
+}
+
+#[test]
+fn test_verify_copied_files() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (bool, &'a str, Option<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            true,
+            "mnt/a",
+            Some("blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+        ),
+        (false, "mnt/a", None),
+        (true, "b", None),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "abc");
+        fs.add_mount_point("mnt");
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_verify(case.0);
+        let mover = FilesMover::new(config, moves(&[("a", case.1)]));
+        let checksum = mover
+            .rename_or_copy(
+                Path::new("a"),
+                Path::new(case.1),
+                false,
+                &Progress::new(false),
+            )
+            .unwrap();
+        assert_eq!(
+            checksum.as_deref(),
+            case.2,
+            "verify: {}, to: {}",
+            case.0,
+            case.1
+        );
+        assert_eq!(fs.files(), vec![PathBuf::from(case.1)]);
+    }
+}
+
+#[test]
+fn test_run_duplicates() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (
+        OnDuplicate,
+        Vec<(&'a str, &'a str)>,
+        bool,
+        Vec<&'a str>,
+        MoveStatus,
+    );
+    let cases: Vec<Case> = vec![
+        (
+            OnDuplicate::DeleteSource,
+            vec![("b", "c")],
+            true,
+            vec!["c", "x"],
+            MoveStatus::Deleted,
+        ),
+        (
+            OnDuplicate::Link,
+            vec![("b", "c")],
+            true,
+            vec!["a", "c", "x"],
+            MoveStatus::Linked,
+        ),
+        (
+            OnDuplicate::DeleteSource,
+            vec![("b", "locked/b")],
+            false,
+            vec!["a", "b", "x"],
+            MoveStatus::Skipped,
+        ),
+        (
+            OnDuplicate::Skip,
+            vec![],
+            false,
+            vec!["a", "b", "x"],
+            MoveStatus::Skipped,
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "same");
+        fs.add_file("b", "other");
+        fs.add_file("x", "same");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_on_duplicate(case.0);
+        let reporter = Reporter::silent(false);
+        let result = FilesMover::new(config, moves(&case.1))
+            .with_duplicates(moves(&[("a", "x")]))
+            .run(&reporter);
+        assert_eq!(result.is_ok(), case.2, "policy: {:?}", case.0);
+        let expected: Vec<PathBuf> = case.3.iter().map(PathBuf::from).collect();
+        let files: Vec<PathBuf> = fs
+            .files()
+            .into_iter()
+            .filter(|file| file != Path::new("locked"))
+            .collect();
+        assert_eq!(files, expected, "policy: {:?}", case.0);
+        assert_eq!(reporter.count(case.4), 1, "policy: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_run_create_dirs() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    type Case<'a> = (bool, Vec<(&'a str, &'a str)>, bool, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            true,
+            vec![("a", "x/y/a"), ("b", "x/b")],
+            true,
+            vec!["x/b", "x/y/a"],
+        ),
+        (false, vec![("a", "x/y/a")], false, vec!["a", "b"]),
+        (true, vec![("a", "b/a")], false, vec!["a", "b"]),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "");
+        fs.add_file("b", "");
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_create_dirs(case.0);
+        let result = FilesMover::new(config, moves(&case.1)).run(&Reporter::silent(false));
+        assert_eq!(result.is_ok(), case.2, "moves: {:?}", case.1);
+        let expected: Vec<PathBuf> = case.3.iter().map(PathBuf::from).collect();
+        assert_eq!(fs.files(), expected, "moves: {:?}", case.1);
+    }
+}
+
+#[test]
+fn test_run_swaps() {
+    use super::file_system::{FileSystem, MemoryFileSystem};
+    use std::sync::Arc;
+
+    // The size of `a` after the run is 2 if `a` and `c` are swapped.
+    type Case<'a> = (Vec<(&'a str, &'a str)>, bool, bool, u64, usize);
+    let cases: Vec<Case> = vec![
+        (vec![("a", "c"), ("c", "a")], false, true, 2, 2),
+        (vec![("c", "a"), ("x", "y"), ("a", "c")], false, true, 2, 3),
+        (
+            vec![("a", "c"), ("c", "a"), ("x", "locked/x")],
+            false,
+            false,
+            1,
+            0,
+        ),
+        (
+            vec![("a", "c"), ("c", "a"), ("x", "locked/x")],
+            true,
+            false,
+            2,
+            2,
+        ),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("a", "a");
+        fs.add_file("c", "cc");
+        fs.add_file("x", "x");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let config = Config::new()
+            .with_file_system(fs.clone())
+            .with_keep_going(case.1);
+        let reporter = Reporter::silent(false);
+        let result = FilesMover::new(config, moves(&case.0)).run(&reporter);
+        assert_eq!(result.is_ok(), case.2, "moves: {:?}", case.0);
+        let len = fs.metadata(Path::new("a")).unwrap().len;
+        assert_eq!(len, case.3, "moves: {:?}", case.0);
+        let moved = reporter.count(MoveStatus::Moved);
+        assert_eq!(moved, case.4, "moves: {:?}", case.0);
+    }
 }
@@ -0,0 +1,1039 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Type of a file system entry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// `FileMetadata` is the part of file metadata used by mmv: type, size, mtime, permissions
+/// and inode.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct FileMetadata {
+    pub kind: FileKind,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    /// Unix permission bits (e.g. `0o644`).
+    pub mode: u32,
+    /// Inode number, `None` if the file system has no inodes.
+    pub inode: Option<u64>,
+}
+
+impl FileMetadata {
+    /// Construct new metadata.
+    pub fn new(kind: FileKind, len: u64, modified: Option<SystemTime>, mode: u32) -> Self {
+        FileMetadata {
+            kind,
+            len,
+            modified,
+            mode,
+            inode: None,
+        }
+    }
+
+    /// Construct metadata from metadata of a real file.
+    pub fn from_std(metadata: &fs::Metadata) -> Self {
+        FileMetadata {
+            kind: FileKind::from_std(metadata.file_type()),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            mode: file_mode(metadata),
+            inode: file_inode(metadata),
+        }
+    }
+}
+
+impl FileKind {
+    fn from_std(file_type: fs::FileType) -> Self {
+        if file_type.is_file() {
+            FileKind::File
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else if file_type.is_symlink() {
+            FileKind::Symlink
+        } else {
+            FileKind::Other
+        }
+    }
+}
+
+/// Function returns permission bits of a file.
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+/// Function returns permission bits of a file.
+/// There are no unix permissions on this platform, so they are derived from the readonly flag.
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o666
+    }
+}
+
+/// Function returns the inode number of a file.
+#[cfg(unix)]
+fn file_inode(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+/// Function returns the inode number of a file, there are no inodes on this platform.
+#[cfg(not(unix))]
+fn file_inode(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// `DirEntry` is an entry of a directory listing.
+/// Entries of the real file system read the type and metadata lazily, only when they are requested.
+pub struct DirEntry {
+    name: OsString,
+    inner: DirEntryInner,
+}
+
+enum DirEntryInner {
+    Real(fs::DirEntry),
+    Virtual(FileMetadata),
+}
+
+impl DirEntry {
+    /// Construct an entry of a virtual file system.
+    pub fn new(name: impl Into<OsString>, metadata: FileMetadata) -> Self {
+        DirEntry {
+            name: name.into(),
+            inner: DirEntryInner::Virtual(metadata),
+        }
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Function returns the type of the entry, symlinks are not followed.
+    pub fn kind(&self) -> io::Result<FileKind> {
+        match &self.inner {
+            DirEntryInner::Real(entry) => entry.file_type().map(FileKind::from_std),
+            DirEntryInner::Virtual(metadata) => Ok(metadata.kind),
+        }
+    }
+
+    /// Function returns metadata of the entry, symlinks are not followed.
+    pub fn metadata(&self) -> io::Result<FileMetadata> {
+        match &self.inner {
+            DirEntryInner::Real(entry) => entry.metadata().map(|m| FileMetadata::from_std(&m)),
+            DirEntryInner::Virtual(metadata) => Ok(metadata.clone()),
+        }
+    }
+}
+
+/// Streaming listing of a directory.
+pub type ReadDir<'a> = Box<dyn Iterator<Item = io::Result<DirEntry>> + Send + 'a>;
+
+/// `FileSystem` is the set of file system operations used by `FileMatcher` and `FilesMover`.
+/// `RealFileSystem` calls `std::fs`, `MemoryFileSystem` keeps files in memory.
+/// Symlinks are never followed.
+/// Usage:
+/// ```rust
+/// use mmv::operations::file_system::{FileSystem, MemoryFileSystem};
+/// use std::path::Path;
+///
+/// let fs = MemoryFileSystem::new();
+/// fs.add_file("notes/a.txt", "content");
+/// fs.rename(Path::new("notes/a.txt"), Path::new("notes/b.txt")).unwrap();
+/// assert!(fs.exists(Path::new("notes/b.txt")));
+/// ```
+pub trait FileSystem: Send + Sync {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>>;
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+    fn exists(&self, path: &Path) -> bool;
+    /// Function opens a file for reading, it is used to read content of matched files.
+    /// The file is seekable, so parsers of headers and tags do not read it in full.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>>;
+    /// Function creates or truncates a file and writes the content, it is used to write plans.
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+    /// Function returns the directory relative paths are resolved from.
+    fn current_dir(&self) -> io::Result<PathBuf>;
+    /// Function renames a file, it fails with `io::ErrorKind::CrossesDevices` if the target
+    /// is on another file system.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Function renames a file only if the target does not exist, it fails with
+    /// `io::ErrorKind::AlreadyExists` otherwise. The check and the rename are atomic,
+    /// so a file created by another process in between is never replaced. It fails with
+    /// `io::ErrorKind::Unsupported` if the file system can not rename a file this way.
+    fn rename_noreplace(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Function atomically swaps two files, it fails with `io::ErrorKind::Unsupported`
+    /// if the file system can not do it.
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    /// Function creates a directory and its missing parents, existing directories are not errors.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    /// Function copies content, permissions and modification time of a file to a new file,
+    /// it fails with `io::ErrorKind::AlreadyExists` if the target exists. It is used
+    /// to move files across file systems. `on_copied` is called with the number of bytes
+    /// of every copied chunk. It returns the size of the file.
+    fn copy_file(&self, from: &Path, to: &Path, on_copied: &mut dyn FnMut(u64)) -> io::Result<u64>;
+    /// Function creates a hard link `link` to the file `original`, it fails with
+    /// `io::ErrorKind::AlreadyExists` if `link` exists.
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()>;
+}
+
+/// `ReadSeek` is a readable and seekable file opened with `FileSystem::open`.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// Size of a chunk of `RealFileSystem::copy_file`.
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
+/// `RealFileSystem` is the file system of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>> {
+        let entries = fs::read_dir(path)?.map(|entry| {
+            entry.map(|entry| DirEntry {
+                name: entry.file_name(),
+                inner: DirEntryInner::Real(entry),
+            })
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        fs::symlink_metadata(path).map(|metadata| FileMetadata::from_std(&metadata))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(path, content)
+    }
+
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        env::current_dir()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn rename_noreplace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        match linux::renameat2(from, to, libc::RENAME_NOREPLACE) {
+            Err(err) if linux::is_unsupported(&err) => {}
+            result => return result,
+        }
+        #[cfg(target_os = "macos")]
+        match macos::renamex_np(from, to, libc::RENAME_EXCL) {
+            Err(err) if macos::is_unsupported(&err) => {}
+            result => return result,
+        }
+        rename_noreplace_fallback(from, to)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()> {
+        match linux::renameat2(first, second, libc::RENAME_EXCHANGE) {
+            Err(err) if linux::is_unsupported(&err) => {
+                Err(io::Error::from(io::ErrorKind::Unsupported))
+            }
+            result => result,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn exchange(&self, _first: &Path, _second: &Path) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        fs::hard_link(original, link)
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path, on_copied: &mut dyn FnMut(u64)) -> io::Result<u64> {
+        let mut source = fs::File::open(from)?;
+        let metadata = source.metadata()?;
+        let mut target = fs::File::create_new(to)?;
+        let result = (|| {
+            let mut buffer = vec![0; COPY_CHUNK_SIZE];
+            let mut copied = 0;
+            loop {
+                let count = match source.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(count) => count,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                };
+                target.write_all(&buffer[..count])?;
+                copied += count as u64;
+                on_copied(count as u64);
+            }
+            target.sync_all()?;
+            target.set_permissions(metadata.permissions())?;
+            if let Ok(modified) = metadata.modified() {
+                target.set_modified(modified)?;
+            }
+            Ok(copied)
+        })();
+        if result.is_err() {
+            // A partial copy is removed, the source is kept.
+            let _ = fs::remove_file(to);
+        }
+        result
+    }
+}
+
+/// Function renames a file without replacing the target on file systems without an atomic
+/// no-replace rename. A hard link is never created over an existing file, a directory takes
+/// the name of an empty directory created for it. Files of file systems without hard links
+/// are not renamed: the target could be created between a check and the rename.
+fn rename_noreplace_fallback(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from).inspect_err(|_| {
+            let _ = fs::remove_file(to);
+        }),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::AlreadyExists | io::ErrorKind::CrossesDevices
+            ) =>
+        {
+            Err(err)
+        }
+        Err(_) if to.symlink_metadata().is_ok() => {
+            Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        }
+        Err(_)
+            if from
+                .symlink_metadata()
+                .is_ok_and(|metadata| metadata.is_dir()) =>
+        {
+            rename_dir_noreplace(from, to)
+        }
+        Err(err) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "the file system can not rename files without replacing existing ones \
+                 (no atomic rename and no hard links: {}), use --force to replace them",
+                err
+            ),
+        )),
+    }
+}
+
+/// Function renames a directory without replacing the target: the name is taken by creating
+/// an empty directory, and a directory is renamed only over an empty directory, so a file
+/// or a non-empty directory created in between is never replaced.
+#[cfg(unix)]
+fn rename_dir_noreplace(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    fs::rename(from, to).inspect_err(|_| {
+        let _ = fs::remove_dir(to);
+    })
+}
+
+/// Function renames a directory without replacing the target, it is not supported
+/// without an atomic no-replace rename.
+#[cfg(not(unix))]
+fn rename_dir_noreplace(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the file system can not rename directories without replacing existing ones, \
+         use --force to replace them",
+    ))
+}
+
+/// Atomic renames of macOS: `renamex_np(2)` with `RENAME_EXCL`.
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Function calls `renamex_np` with the flags.
+    pub fn renamex_np(from: &Path, to: &Path, flags: libc::c_uint) -> io::Result<()> {
+        let from = CString::new(from.as_os_str().as_bytes())?;
+        let to = CString::new(to.as_os_str().as_bytes())?;
+        // SAFETY: both paths are valid NUL-terminated strings that outlive the call.
+        let result = unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), flags) };
+        if result == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Function checks if the file system does not support the flags.
+    pub fn is_unsupported(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(libc::ENOTSUP | libc::EINVAL))
+    }
+}
+
+/// Atomic renames of Linux: `renameat2(2)` with `RENAME_NOREPLACE` and `RENAME_EXCHANGE`.
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Function calls `renameat2` with the flags, paths are relative to the current directory.
+    /// The system call is used directly, as old C libraries have no wrapper for it.
+    pub fn renameat2(from: &Path, to: &Path, flags: libc::c_uint) -> io::Result<()> {
+        let from = CString::new(from.as_os_str().as_bytes())?;
+        let to = CString::new(to.as_os_str().as_bytes())?;
+        // SAFETY: both paths are valid NUL-terminated strings that outlive the call.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_renameat2,
+                libc::AT_FDCWD,
+                from.as_ptr(),
+                libc::AT_FDCWD,
+                to.as_ptr(),
+                flags,
+            )
+        };
+        if result == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Function checks if the kernel (`ENOSYS`) or the file system (`EINVAL`) does not
+    /// support the flags.
+    pub fn is_unsupported(err: &io::Error) -> bool {
+        matches!(err.raw_os_error(), Some(libc::ENOSYS | libc::EINVAL))
+    }
+}
+
+/// `MemoryFileSystem` is a virtual file system, it is used to test moves deterministically
+/// and to run mmv against file systems that are not mounted.
+/// Relative paths are relative to the root of the file system, `.` components are ignored.
+/// Moving or deleting entries in a directory without the write permission (`0o200`) fails
+/// with `PermissionDenied`. Renames between mount points (see `add_mount_point`) fail with
+/// `CrossesDevices`, as on real file systems.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    entries: Mutex<BTreeMap<PathBuf, MemoryEntry>>,
+    mount_points: Mutex<Vec<PathBuf>>,
+}
+
+/// An entry of `MemoryFileSystem`, directories have no content.
+#[derive(Debug, Clone)]
+struct MemoryEntry {
+    metadata: FileMetadata,
+    content: Arc<[u8]>,
+}
+
+impl MemoryFileSystem {
+    /// Construct a new empty file system.
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    /// Function adds a file (and its parent directories) with the content.
+    pub fn add_file(&self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) {
+        let path = normalize(path.as_ref());
+        self.add_parents(&path);
+        let metadata = FileMetadata::new(
+            FileKind::File,
+            content.as_ref().len() as u64,
+            Some(SystemTime::now()),
+            0o644,
+        );
+        let content = Arc::from(content.as_ref());
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path, MemoryEntry { metadata, content });
+    }
+
+    /// Function adds a directory and its parent directories.
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let path = normalize(path.as_ref());
+        self.add_parents(&path);
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(path)
+            .or_insert_with(directory_entry);
+    }
+
+    /// Function sets permission bits of an entry.
+    pub fn set_mode(&self, path: impl AsRef<Path>, mode: u32) {
+        if let Some(entry) = self
+            .entries
+            .lock()
+            .unwrap()
+            .get_mut(&normalize(path.as_ref()))
+        {
+            entry.metadata.mode = mode;
+        }
+    }
+
+    /// Function makes the directory a root of another file system.
+    pub fn add_mount_point(&self, path: impl AsRef<Path>) {
+        let path = normalize(path.as_ref());
+        self.add_dir(&path);
+        self.mount_points.lock().unwrap().push(path);
+    }
+
+    /// Function returns the mount point of the file system the path belongs to.
+    fn mount_point(&self, path: &Path) -> PathBuf {
+        self.mount_points
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|mount_point| path.starts_with(mount_point))
+            .max_by_key(|mount_point| mount_point.components().count())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Function renames an entry with its children, see `FileSystem::rename_noreplace`.
+    fn rename_entry(&self, from: &Path, to: &Path, noreplace: bool) -> io::Result<()> {
+        let (from, to) = (normalize(from), normalize(to));
+        let crosses_devices = self.mount_point(&from) != self.mount_point(&to);
+        let mut entries = self.entries.lock().unwrap();
+        let Some(metadata) = entries.get(&from).map(|entry| entry.metadata.clone()) else {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        };
+        check_writable(&entries, &from)?;
+        check_writable(&entries, &to)?;
+        if noreplace && entries.contains_key(&to) {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+        if from == to {
+            return Ok(());
+        }
+        if crosses_devices {
+            return Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        }
+
+        match entries.get(&to) {
+            Some(target) if target.metadata.kind == FileKind::Directory => {
+                if metadata.kind != FileKind::Directory {
+                    return Err(io::Error::from(io::ErrorKind::IsADirectory));
+                }
+                if entries.keys().any(|path| parent_of(path) == to) {
+                    return Err(io::Error::from(io::ErrorKind::DirectoryNotEmpty));
+                }
+            }
+            Some(_) if metadata.kind == FileKind::Directory => {
+                return Err(io::Error::from(io::ErrorKind::NotADirectory));
+            }
+            _ => {}
+        }
+        if to.starts_with(&from) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        // The entry is moved with all its children.
+        let moved: Vec<PathBuf> = entries
+            .keys()
+            .filter(|path| path.starts_with(&from))
+            .cloned()
+            .collect();
+        entries.remove(&to);
+        for path in moved {
+            let entry = entries.remove(&path).unwrap();
+            let new_path = to.join(path.strip_prefix(&from).unwrap());
+            entries.insert(new_path, entry);
+        }
+        Ok(())
+    }
+
+    /// Function returns paths of all files, it is used to check results of moves.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.metadata.kind == FileKind::File)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    fn add_parents(&self, path: &Path) {
+        let mut entries = self.entries.lock().unwrap();
+        for parent in path.ancestors().skip(1) {
+            if !parent.as_os_str().is_empty() {
+                entries
+                    .entry(parent.to_owned())
+                    .or_insert_with(directory_entry);
+            }
+        }
+    }
+}
+
+fn directory_entry() -> MemoryEntry {
+    MemoryEntry {
+        metadata: FileMetadata::new(FileKind::Directory, 0, Some(SystemTime::now()), 0o755),
+        content: Arc::from([]),
+    }
+}
+
+/// Function removes `.` components, so `./a/b` and `a/b` are the same path.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Function returns the parent directory of a normalized path, the root is an empty path.
+fn parent_of(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new(""))
+}
+
+/// Function checks that entries may be added to or removed from the parent directory of the path.
+fn check_writable(entries: &BTreeMap<PathBuf, MemoryEntry>, path: &Path) -> io::Result<()> {
+    let parent = parent_of(path);
+    if parent.as_os_str().is_empty() {
+        return Ok(());
+    }
+    match entries.get(parent) {
+        Some(entry) if entry.metadata.kind != FileKind::Directory => {
+            Err(io::Error::from(io::ErrorKind::NotADirectory))
+        }
+        Some(entry) if entry.metadata.mode & 0o200 == 0 => {
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        }
+        Some(_) => Ok(()),
+        None => Err(io::Error::from(io::ErrorKind::NotFound)),
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read_dir(&self, path: &Path) -> io::Result<ReadDir<'_>> {
+        let path = normalize(path);
+        let entries = self.entries.lock().unwrap();
+        if !path.as_os_str().is_empty() {
+            match entries.get(&path) {
+                Some(entry) if entry.metadata.kind == FileKind::Directory => {}
+                Some(_) => return Err(io::Error::from(io::ErrorKind::NotADirectory)),
+                None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        }
+
+        let children: Vec<io::Result<DirEntry>> = entries
+            .iter()
+            .filter(|(child, _)| parent_of(child) == path)
+            .filter_map(|(child, entry)| {
+                let name = child.file_name()?;
+                Some(Ok(DirEntry::new(name, entry.metadata.clone())))
+            })
+            .collect();
+        Ok(Box::new(children.into_iter()))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&normalize(path))
+            .map(|entry| entry.metadata.clone())
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        path.as_os_str().is_empty() || self.entries.lock().unwrap().contains_key(&path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn ReadSeek>> {
+        match self.entries.lock().unwrap().get(&normalize(path)) {
+            Some(entry) if entry.metadata.kind == FileKind::Directory => {
+                Err(io::Error::from(io::ErrorKind::IsADirectory))
+            }
+            Some(entry) => Ok(Box::new(io::Cursor::new(entry.content.clone()))),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let path = normalize(path);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&path) {
+            Some(entry) if entry.metadata.kind == FileKind::Directory => {
+                return Err(io::Error::from(io::ErrorKind::IsADirectory))
+            }
+            Some(_) => {}
+            None => check_writable(&entries, &path)?,
+        }
+        let metadata = FileMetadata::new(
+            FileKind::File,
+            content.len() as u64,
+            Some(SystemTime::now()),
+            0o644,
+        );
+        let content = Arc::from(content);
+        entries.insert(path, MemoryEntry { metadata, content });
+        Ok(())
+    }
+
+    /// Relative paths are relative to the root of the file system.
+    fn current_dir(&self) -> io::Result<PathBuf> {
+        Ok(PathBuf::from("/"))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.rename_entry(from, to, false)
+    }
+
+    fn rename_noreplace(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.rename_entry(from, to, true)
+    }
+
+    fn exchange(&self, first: &Path, second: &Path) -> io::Result<()> {
+        let (first, second) = (normalize(first), normalize(second));
+        let crosses_devices = self.mount_point(&first) != self.mount_point(&second);
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.contains_key(&first) || !entries.contains_key(&second) {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        check_writable(&entries, &first)?;
+        check_writable(&entries, &second)?;
+        if first == second {
+            return Ok(());
+        }
+        if crosses_devices {
+            return Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        }
+        if first.starts_with(&second) || second.starts_with(&first) {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput));
+        }
+
+        // Both entries are swapped with all their children.
+        let swapped: Vec<(PathBuf, PathBuf)> = entries
+            .keys()
+            .filter_map(|path| {
+                let (prefix, other) = if path.starts_with(&first) {
+                    (&first, &second)
+                } else if path.starts_with(&second) {
+                    (&second, &first)
+                } else {
+                    return None;
+                };
+                Some((path.clone(), other.join(path.strip_prefix(prefix).unwrap())))
+            })
+            .collect();
+        let removed: Vec<MemoryEntry> = swapped
+            .iter()
+            .map(|(path, _)| entries.remove(path).unwrap())
+            .collect();
+        for ((_, new_path), entry) in swapped.into_iter().zip(removed) {
+            entries.insert(new_path, entry);
+        }
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&path) {
+            Some(entry) if entry.metadata.kind == FileKind::Directory => {
+                Err(io::Error::from(io::ErrorKind::IsADirectory))
+            }
+            Some(_) => {
+                check_writable(&entries, &path)?;
+                entries.remove(&path);
+                Ok(())
+            }
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = normalize(path);
+        let mut entries = self.entries.lock().unwrap();
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            match entries.get(ancestor) {
+                Some(entry) if entry.metadata.kind == FileKind::Directory => {}
+                Some(_) => return Err(io::Error::from(io::ErrorKind::NotADirectory)),
+                None => {
+                    check_writable(&entries, ancestor)?;
+                    entries.insert(ancestor.to_owned(), directory_entry());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_file(&self, from: &Path, to: &Path, on_copied: &mut dyn FnMut(u64)) -> io::Result<u64> {
+        let (from, to) = (normalize(from), normalize(to));
+        let mut entries = self.entries.lock().unwrap();
+        let entry = match entries.get(&from) {
+            Some(entry) if entry.metadata.kind == FileKind::Directory => {
+                return Err(io::Error::from(io::ErrorKind::IsADirectory))
+            }
+            Some(entry) => entry.clone(),
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        check_writable(&entries, &to)?;
+        if entries.contains_key(&to) {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+
+        let len = entry.metadata.len;
+        entries.insert(to, entry);
+        on_copied(len);
+        Ok(len)
+    }
+
+    fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (original, link) = (normalize(original), normalize(link));
+        if self.mount_point(&original) != self.mount_point(&link) {
+            return Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let entry = match entries.get(&original) {
+            Some(entry) if entry.metadata.kind == FileKind::Directory => {
+                return Err(io::Error::from(io::ErrorKind::PermissionDenied))
+            }
+            Some(entry) => entry.clone(),
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+        check_writable(&entries, &link)?;
+        if entries.contains_key(&link) {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+        }
+        entries.insert(link, entry);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_memory_file_system_rename() {
+    type Case<'a> = (&'a str, &'a str, Option<io::ErrorKind>);
+    let cases: Vec<Case> = vec![
+        ("dir/a.txt", "dir/c.txt", None),
+        ("./dir/a.txt", "dir/sub/a.txt", None),
+        ("dir/a.txt", "dir/b.txt", None),
+        (
+            "dir/missing.txt",
+            "dir/c.txt",
+            Some(io::ErrorKind::NotFound),
+        ),
+        ("dir/a.txt", "other/a.txt", Some(io::ErrorKind::NotFound)),
+        ("dir/a.txt", "dir/sub", Some(io::ErrorKind::IsADirectory)),
+        (
+            "dir/a.txt",
+            "locked/a.txt",
+            Some(io::ErrorKind::PermissionDenied),
+        ),
+    ];
+
+    for case in cases {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("dir/a.txt", "a");
+        fs.add_file("dir/b.txt", "b");
+        fs.add_dir("dir/sub");
+        fs.add_dir("locked");
+        fs.set_mode("locked", 0o555);
+
+        let result = fs.rename(Path::new(case.0), Path::new(case.1));
+        assert_eq!(
+            result.err().map(|err| err.kind()),
+            case.2,
+            "rename: {} -> {}",
+            case.0,
+            case.1
+        );
+        if case.2.is_none() {
+            assert!(!fs.exists(Path::new(case.0)), "rename: {}", case.0);
+            assert!(fs.exists(Path::new(case.1)), "rename: {}", case.1);
+        }
+    }
+}
+
+#[test]
+fn test_real_copy_file() {
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    let directory = TempDir::new("copy_file").unwrap();
+    let from = directory.path().join("from.txt");
+    let to = directory.path().join("to.txt");
+    fs::write(&from, vec![b'x'; COPY_CHUNK_SIZE + 10]).unwrap();
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&from)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    let mut chunks = Vec::new();
+    let copied = RealFileSystem
+        .copy_file(&from, &to, &mut |bytes| chunks.push(bytes))
+        .unwrap();
+    assert_eq!(copied, COPY_CHUNK_SIZE as u64 + 10);
+    assert_eq!(chunks, [COPY_CHUNK_SIZE as u64, 10]);
+    assert_eq!(fs::read(&to).unwrap(), fs::read(&from).unwrap());
+    assert_eq!(fs::metadata(&to).unwrap().modified().unwrap(), modified);
+
+    let missing = directory.path().join("missing.txt");
+    let result = RealFileSystem.copy_file(&missing, &to, &mut |_| {});
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_memory_file_system_noreplace_and_exchange() {
+    type Case<'a> = (bool, &'a str, &'a str, Option<io::ErrorKind>);
+    let cases: Vec<Case> = vec![
+        (false, "dir/a.txt", "dir/c.txt", None),
+        (
+            false,
+            "dir/a.txt",
+            "dir/b.txt",
+            Some(io::ErrorKind::AlreadyExists),
+        ),
+        (true, "dir/a.txt", "dir/b.txt", None),
+        (true, "dir/a.txt", "dir/sub", None),
+        (
+            true,
+            "dir/a.txt",
+            "dir/c.txt",
+            Some(io::ErrorKind::NotFound),
+        ),
+        (true, "dir", "dir/sub", Some(io::ErrorKind::InvalidInput)),
+    ];
+
+    for case in cases {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("dir/a.txt", "a");
+        fs.add_file("dir/b.txt", "bb");
+        fs.add_file("dir/sub/x.txt", "x");
+
+        let (from, to) = (Path::new(case.1), Path::new(case.2));
+        let result = if case.0 {
+            fs.exchange(from, to)
+        } else {
+            fs.rename_noreplace(from, to)
+        };
+        assert_eq!(
+            result.err().map(|err| err.kind()),
+            case.3,
+            "exchange: {}, {} -> {}",
+            case.0,
+            case.1,
+            case.2
+        );
+    }
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("dir/a.txt", "a");
+    fs.add_file("dir/sub/x.txt", "x");
+    fs.exchange(Path::new("dir/a.txt"), Path::new("dir/sub"))
+        .unwrap();
+    let expected: Vec<PathBuf> = ["dir/a.txt/x.txt", "dir/sub"]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(fs.files(), expected);
+}
+
+#[test]
+fn test_rename_noreplace_fallback() {
+    use tempdir::TempDir;
+
+    let directory = TempDir::new("rename_noreplace").unwrap();
+    let path = |name: &str| directory.path().join(name);
+    fs::write(path("a.txt"), "a").unwrap();
+    fs::write(path("b.txt"), "b").unwrap();
+    fs::create_dir(path("dir")).unwrap();
+    fs::write(path("dir/c.txt"), "c").unwrap();
+    fs::create_dir(path("empty")).unwrap();
+
+    type Case<'a> = (&'a str, &'a str, Option<io::ErrorKind>);
+    let cases: Vec<Case> = vec![
+        ("a.txt", "b.txt", Some(io::ErrorKind::AlreadyExists)),
+        ("a.txt", "c.txt", None),
+        ("dir", "b.txt", Some(io::ErrorKind::AlreadyExists)),
+        ("dir", "empty", Some(io::ErrorKind::AlreadyExists)),
+        ("dir", "moved", None),
+    ];
+
+    for case in cases {
+        let result = rename_noreplace_fallback(&path(case.0), &path(case.1));
+        assert_eq!(
+            result.err().map(|err| err.kind()),
+            case.2,
+            "from: {}, to: {}",
+            case.0,
+            case.1
+        );
+    }
+    assert_eq!(fs::read_to_string(path("b.txt")).unwrap(), "b");
+    assert_eq!(fs::read_to_string(path("c.txt")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(path("moved/c.txt")).unwrap(), "c");
+    assert!(path("empty").is_dir());
+}
+
+#[test]
+fn test_real_rename_noreplace_and_exchange() {
+    use tempdir::TempDir;
+
+    let directory = TempDir::new("rename_noreplace").unwrap();
+    let (a, b, c) = (
+        directory.path().join("a.txt"),
+        directory.path().join("b.txt"),
+        directory.path().join("c.txt"),
+    );
+    fs::write(&a, "a").unwrap();
+    fs::write(&b, "b").unwrap();
+
+    let result = RealFileSystem.rename_noreplace(&a, &b);
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(fs::read_to_string(&b).unwrap(), "b");
+
+    RealFileSystem.rename_noreplace(&a, &c).unwrap();
+    assert!(!a.exists());
+    assert_eq!(fs::read_to_string(&c).unwrap(), "a");
+
+    match RealFileSystem.exchange(&b, &c) {
+        Ok(()) => {
+            assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+            assert_eq!(fs::read_to_string(&c).unwrap(), "b");
+        }
+        Err(err) => assert_eq!(err.kind(), io::ErrorKind::Unsupported),
+    }
+}
@@ -0,0 +1,119 @@
+//! Module hashes file contents for the `#{sha256}`, `#{md5}` and `#{blake3}` placeholders
+//! and for checking copied files with `--verify`.
+use super::file_system::FileSystem;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Size of a chunk of a hashed file.
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hash algorithms of `#{sha256}`, `#{md5}` and `#{blake3}` placeholders and of `--verify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Function returns the algorithm with the name of its placeholder.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "md5" => Some(HashAlgorithm::Md5),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None,
+        }
+    }
+
+    /// Function returns the name of the algorithm, as used in placeholders and `--verify`.
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+/// Function hashes everything read from the reader, the hash is returned as lowercase hex.
+/// Usage:
+/// ```rust
+/// use mmv::operations::hash::{hash_reader, HashAlgorithm};
+///
+/// let hash = hash_reader(HashAlgorithm::Md5, &mut "abc".as_bytes()).unwrap();
+/// assert_eq!(hash, "900150983cd24fb0d6963f7d28e17f72");
+/// ```
+pub fn hash_reader(algorithm: HashAlgorithm, reader: &mut dyn Read) -> io::Result<String> {
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            read_chunks(reader, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Md5 => {
+            let mut hasher = Md5::new();
+            read_chunks(reader, |chunk| hasher.update(chunk))?;
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            read_chunks(reader, |chunk| {
+                hasher.update(chunk);
+            })?;
+            hasher.finalize().as_bytes().to_vec()
+        }
+    };
+    Ok(digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    }))
+}
+
+/// Function hashes a file of the file system.
+pub fn hash_file(
+    file_system: &dyn FileSystem,
+    path: &Path,
+    algorithm: HashAlgorithm,
+) -> io::Result<String> {
+    hash_reader(algorithm, &mut file_system.open(path)?)
+}
+
+/// Function reads the reader to the end chunk by chunk.
+fn read_chunks(reader: &mut dyn Read, mut on_chunk: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut buffer = vec![0; HASH_CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(count) => on_chunk(&buffer[..count]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[test]
+fn test_hash_reader() {
+    let cases: Vec<(HashAlgorithm, &str, &str)> = vec![
+        (
+            HashAlgorithm::Sha256,
+            "abc",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+        (HashAlgorithm::Md5, "", "d41d8cd98f00b204e9800998ecf8427e"),
+        (
+            HashAlgorithm::Blake3,
+            "",
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+        ),
+    ];
+
+    for case in cases {
+        let hash = hash_reader(case.0, &mut case.1.as_bytes()).unwrap();
+        assert_eq!(hash, case.2, "algorithm: {:?}", case.0);
+    }
+}
@@ -1,5 +1,23 @@
 //! Module provides operations for matchin files in a directory based on a pattern and moving them to a new location.
 //! Module defines two structs: [FileMatcher](./file_matcher.rs) and [FilesMover](./file_move.rs)
+//! and a filter stage of the matcher: [FileFilter](./file_filter.rs).
+//! Both access files through the [FileSystem](./file_system.rs) trait.
+//! Pairs of files to move may also be read from a list: [pair_list](./pair_list.rs)
+//! or edited in a text editor: [edit](./edit.rs).
+//! Content of files is hashed for placeholders and `--verify`: [hash](./hash.rs),
+//! EXIF fields of images and tags of audio files are read for placeholders:
+//! [exif](./exif.rs) and [audio](./audio.rs).
+//! Conflicts of a planned batch are resolved by a policy: [conflict](./conflict.rs).
+//! A resolved batch may be saved and applied later: [Plan](./plan.rs).
 
+pub mod audio;
+pub mod conflict;
+pub mod edit;
+pub mod exif;
+pub mod file_filter;
 pub mod file_matcher;
 pub mod file_move;
+pub mod file_system;
+pub mod hash;
+pub mod pair_list;
+pub mod plan;
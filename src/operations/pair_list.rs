@@ -0,0 +1,148 @@
+use super::file_move::MoveFiles;
+use crate::encoding::os_string_from_bytes;
+use crate::errors::{MassMoveError, Operation};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Function reads pairs of files to move from a file or from stdin if the path is `-`.
+/// See `read_pairs` for the format of the list.
+pub fn read_pairs_from_path(
+    path: &Path,
+    nul_separated: bool,
+) -> Result<Vec<MoveFiles>, MassMoveError> {
+    if path == Path::new("-") {
+        return read_pairs(io::stdin().lock(), nul_separated);
+    }
+
+    let file = File::open(path).map_err(|source| MassMoveError::Io {
+        operation: Operation::ReadFile,
+        path: path.to_owned(),
+        source,
+    })?;
+    read_pairs(BufReader::new(file), nul_separated)
+}
+
+/// Function reads pairs of files to move, the list is read as raw bytes.
+/// There are two formats of the list:
+/// 1. lines `from<TAB>to`, empty lines are skipped;
+/// 2. NUL-separated (`nul_separated = true`): `from\0to\0from\0to\0...`, it supports any file names.
+///
+/// Usage:
+/// ```rust
+/// use mmv::operations::pair_list::read_pairs;
+/// use std::path::PathBuf;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let pairs = read_pairs("a.txt\tb.txt\n".as_bytes(), false)?;
+/// assert_eq!(pairs[0].to, PathBuf::from("b.txt"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_pairs(reader: impl Read, nul_separated: bool) -> Result<Vec<MoveFiles>, MassMoveError> {
+    let reader = BufReader::new(reader);
+    if nul_separated {
+        read_nul_separated_pairs(reader)
+    } else {
+        read_tab_separated_pairs(reader)
+    }
+}
+
+/// Function builds a path from raw bytes of the list.
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf, MassMoveError> {
+    os_string_from_bytes(bytes.to_vec()).map(PathBuf::from)
+}
+
+/// Function reads `from<TAB>to` lines.
+fn read_tab_separated_pairs(reader: impl BufRead) -> Result<Vec<MoveFiles>, MassMoveError> {
+    let mut files_to_move = Vec::new();
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let line = line?;
+        let line = line.strip_suffix(b"\r").unwrap_or(&line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split(|&byte| byte == b'\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(from), Some(to), None) if !from.is_empty() && !to.is_empty() => {
+                files_to_move.push(MoveFiles {
+                    from: path_from_bytes(from)?,
+                    to: path_from_bytes(to)?,
+                });
+            }
+            _ => {
+                return Err(MassMoveError::InvalidPairList(format!(
+                    "line {}: expected `from<TAB>to`",
+                    i + 1
+                )))
+            }
+        }
+    }
+    Ok(files_to_move)
+}
+
+/// Function reads `from\0to\0` pairs.
+fn read_nul_separated_pairs(reader: impl BufRead) -> Result<Vec<MoveFiles>, MassMoveError> {
+    let fields: Vec<Vec<u8>> = reader.split(b'\0').collect::<Result<_, _>>()?;
+    if !fields.len().is_multiple_of(2) {
+        return Err(MassMoveError::InvalidPairList(
+            "odd number of NUL-separated paths".to_owned(),
+        ));
+    }
+
+    let mut files_to_move = Vec::with_capacity(fields.len() / 2);
+    for (i, pair) in fields.chunks(2).enumerate() {
+        if pair[0].is_empty() || pair[1].is_empty() {
+            return Err(MassMoveError::InvalidPairList(format!(
+                "pair {}: empty path",
+                i + 1
+            )));
+        }
+        files_to_move.push(MoveFiles {
+            from: path_from_bytes(&pair[0])?,
+            to: path_from_bytes(&pair[1])?,
+        });
+    }
+    Ok(files_to_move)
+}
+
+#[test]
+fn test_read_pairs() {
+    type Case<'a> = (&'a [u8], bool, Option<Vec<(&'a str, &'a str)>>);
+    let cases: Vec<Case> = vec![
+        (b"a\tb\nc\td\n", false, Some(vec![("a", "b"), ("c", "d")])),
+        (
+            b"a\tb\r\n\nc d\td\n",
+            false,
+            Some(vec![("a", "b"), ("c d", "d")]),
+        ),
+        (b"a\tb", false, Some(vec![("a", "b")])),
+        (b"a b\n", false, None),
+        (b"a\tb\tc\n", false, None),
+        (b"\tb\n", false, None),
+        (
+            b"a\0b\0c\td\0e\nf\0",
+            true,
+            Some(vec![("a", "b"), ("c\td", "e\nf")]),
+        ),
+        (b"a\0b\0c\0", true, None),
+        (b"", true, Some(vec![])),
+    ];
+
+    for case in cases {
+        let result = read_pairs(case.0, case.1).ok().map(|files_to_move| {
+            files_to_move
+                .iter()
+                .map(|pair| (pair.from.clone(), pair.to.clone()))
+                .collect::<Vec<_>>()
+        });
+        let expected = case.2.map(|pairs| {
+            pairs
+                .iter()
+                .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(result, expected, "list: {:?}", case.0);
+    }
+}
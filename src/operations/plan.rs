@@ -0,0 +1,433 @@
+use super::conflict::{OnDuplicate, Resolution};
+use super::file_move::MoveFiles;
+use super::file_system::{FileMetadata, FileSystem};
+use crate::config::Config;
+use crate::encoding;
+use crate::errors::{MassMoveError, Operation};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Version of the plan format, plans of other versions are refused.
+const PLAN_VERSION: u32 = 1;
+
+/// `Plan` is a serialisable rename plan: resolved pairs of files with fingerprints of sources.
+/// It is written with `--plan-out` and applied later with `--apply`.
+/// With `--on-duplicate delete-source` or `link` the policy and the pairs whose target is
+/// a duplicate of the source are recorded too, with fingerprints of both files.
+/// Files are read and written on the file system of the `Config`.
+/// The plan is stored as JSON:
+/// ```json
+/// {
+///   "version": 1,
+///   "cwd": "/home/user/photos",
+///   "patterns": [{ "source": "./IMG_*.jpg", "target": "./photo-#1.jpg" }],
+///   "pair_list": null,
+///   "pairs": [
+///     {
+///       "from": "./IMG_1.jpg",
+///       "to": "./photo-1.jpg",
+///       "fingerprint": { "size": 1024, "mtime_secs": 1700000000, "mtime_nanos": 0, "inode": 42 }
+///     }
+///   ],
+///   "on_duplicate": "delete-source",
+///   "duplicates": [
+///     {
+///       "from": "./IMG_2.jpg",
+///       "to": "./photo-2.jpg",
+///       "fingerprint": { "size": 2048, "mtime_secs": 1700000000, "mtime_nanos": 0, "inode": 43 },
+///       "target_fingerprint": { "size": 2048, "mtime_secs": 1700000100, "mtime_nanos": 0, "inode": 44 }
+///     }
+///   ]
+/// }
+/// ```
+///
+/// Usage:
+/// ```rust
+/// use mmv::operations::plan::Plan;
+/// use mmv::{Config, MoveFiles};
+/// # use tempdir::TempDir;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// # let directory = TempDir::new("mmv-doc")?;
+/// # let plan_path = directory.path().join("rename.plan.json");
+/// # let from = directory.path().join("a.txt");
+/// # std::fs::File::create(&from)?;
+/// let config = Config::new();
+/// let files_to_move = vec![MoveFiles::new(from, directory.path().join("b.txt"))];
+/// let plan = Plan::new(&config, Vec::new(), None, &files_to_move)?;
+/// plan.write_to(&config, &plan_path)?;
+///
+/// let resolution = Plan::read_from(&config, &plan_path)?.into_resolution(&config)?;
+/// assert_eq!(resolution.files_to_move.len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    version: u32,
+    cwd: PathBuf,
+    patterns: Vec<PlanPattern>,
+    pair_list: Option<PathBuf>,
+    pairs: Vec<PlannedMove>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_duplicate: Option<OnDuplicate>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    duplicates: Vec<PlannedDuplicate>,
+}
+
+/// Source and target patterns the plan is built from.
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PlanPattern {
+    pub source: String,
+    pub target: String,
+}
+
+/// Pair of files in the plan.
+#[derive(Debug, Serialize, Deserialize)]
+struct PlannedMove {
+    from: PathBuf,
+    to: PathBuf,
+    fingerprint: Fingerprint,
+}
+
+/// Pair of files whose target is a duplicate of the source, the target must not change either.
+#[derive(Debug, Serialize, Deserialize)]
+struct PlannedDuplicate {
+    from: PathBuf,
+    to: PathBuf,
+    fingerprint: Fingerprint,
+    target_fingerprint: Fingerprint,
+}
+
+/// Fingerprint of a source file, the file must not change between planning and applying.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Fingerprint {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    inode: Option<u64>,
+}
+
+impl PlanPattern {
+    /// Construct a pair of source and target patterns.
+    pub fn new(source: impl Into<String>, target: impl Into<String>) -> Self {
+        PlanPattern {
+            source: source.into(),
+            target: target.into(),
+        }
+    }
+
+    /// Construct a pair of patterns from command line arguments,
+    /// the plan is a text file, so patterns must be valid UTF-8.
+    pub fn from_os_str(source: &OsStr, target: &OsStr) -> Result<Self, MassMoveError> {
+        Ok(PlanPattern::new(plan_str(source)?, plan_str(target)?))
+    }
+}
+
+impl Fingerprint {
+    /// Construct a fingerprint of a file.
+    fn read(file_system: &dyn FileSystem, path: &Path) -> Result<Self, MassMoveError> {
+        let metadata = file_system
+            .metadata(path)
+            .map_err(|source| MassMoveError::Io {
+                operation: Operation::ReadMetadata,
+                path: path.to_owned(),
+                source,
+            })?;
+        Ok(Fingerprint::from_metadata(&metadata))
+    }
+
+    /// Construct a fingerprint of a file from its metadata.
+    fn from_metadata(metadata: &FileMetadata) -> Self {
+        let mtime = metadata
+            .modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+
+        Fingerprint {
+            size: metadata.len,
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            inode: metadata.inode,
+        }
+    }
+}
+
+/// Function returns a string for the plan, the plan is a text file, so it must be valid UTF-8.
+fn plan_str(value: &OsStr) -> Result<&str, MassMoveError> {
+    value.to_str().ok_or_else(|| {
+        MassMoveError::InvalidEncoding(encoding::escape_bytes(encoding::os_str_bytes(value)))
+    })
+}
+
+/// Function returns a path for the plan, see `plan_str`.
+fn plan_path(path: &Path) -> Result<PathBuf, MassMoveError> {
+    plan_str(path.as_os_str()).map(PathBuf::from)
+}
+
+impl Plan {
+    /// Construct a plan from pairs of files to move, fingerprints of sources are read now.
+    pub fn new(
+        config: &Config,
+        patterns: Vec<PlanPattern>,
+        pair_list: Option<&Path>,
+        files_to_move: &[MoveFiles],
+    ) -> Result<Self, MassMoveError> {
+        let file_system = config.file_system().as_ref();
+        let mut pairs = Vec::with_capacity(files_to_move.len());
+        for file_pair in files_to_move {
+            pairs.push(PlannedMove {
+                from: plan_path(&file_pair.from)?,
+                to: plan_path(&file_pair.to)?,
+                fingerprint: Fingerprint::read(file_system, &file_pair.from)?,
+            });
+        }
+
+        Ok(Plan {
+            version: PLAN_VERSION,
+            cwd: plan_path(&file_system.current_dir()?)?,
+            patterns,
+            pair_list: pair_list.map(plan_path).transpose()?,
+            pairs,
+            on_duplicate: None,
+            duplicates: Vec::new(),
+        })
+    }
+
+    /// Function records the `Config::on_duplicate` policy and pairs whose target is a duplicate
+    /// of the source (`Resolution::duplicates`), fingerprints of both files are read now.
+    pub fn with_duplicates(
+        mut self,
+        config: &Config,
+        duplicates: &[MoveFiles],
+    ) -> Result<Self, MassMoveError> {
+        let file_system = config.file_system().as_ref();
+        self.on_duplicate = config.on_duplicate();
+        self.duplicates = Vec::with_capacity(duplicates.len());
+        for file_pair in duplicates {
+            self.duplicates.push(PlannedDuplicate {
+                from: plan_path(&file_pair.from)?,
+                to: plan_path(&file_pair.to)?,
+                fingerprint: Fingerprint::read(file_system, &file_pair.from)?,
+                target_fingerprint: Fingerprint::read(file_system, &file_pair.to)?,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Function returns the recorded `--on-duplicate` policy.
+    pub fn on_duplicate(&self) -> Option<OnDuplicate> {
+        self.on_duplicate
+    }
+
+    /// Function writes the plan as JSON to a file.
+    pub fn write_to(&self, config: &Config, path: &Path) -> Result<(), MassMoveError> {
+        let mut content = serde_json::to_string_pretty(self)
+            .map_err(|err| MassMoveError::InvalidPlan(err.to_string()))?;
+        content.push('\n');
+        config
+            .file_system()
+            .write(path, content.as_bytes())
+            .map_err(|source| MassMoveError::Io {
+                operation: Operation::WriteFile,
+                path: path.to_owned(),
+                source,
+            })
+    }
+
+    /// Function reads a plan written by `write_to`.
+    pub fn read_from(config: &Config, path: &Path) -> Result<Self, MassMoveError> {
+        let mut content = Vec::new();
+        config
+            .file_system()
+            .open(path)
+            .and_then(|mut file| file.read_to_end(&mut content))
+            .map_err(|source| MassMoveError::Io {
+                operation: Operation::ReadFile,
+                path: path.to_owned(),
+                source,
+            })?;
+        let plan: Plan = serde_json::from_slice(&content)
+            .map_err(|err| MassMoveError::InvalidPlan(format!("{}: {}", path.display(), err)))?;
+
+        if plan.version != PLAN_VERSION {
+            return Err(MassMoveError::InvalidPlan(format!(
+                "unsupported version {}, expected {}",
+                plan.version, PLAN_VERSION
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Function checks that the filesystem did not drift from the plan: the plan is applied in
+    /// the same directory and every source (and target of a duplicate) exists with the same
+    /// size, mtime and inode. All changed files are aggregated.
+    pub fn check_drift(&self, config: &Config) -> Result<(), MassMoveError> {
+        let file_system = config.file_system();
+        let cwd = file_system.current_dir()?;
+        if cwd != self.cwd {
+            return Err(MassMoveError::PlanDrift(format!(
+                "plan is made in {}, current directory is {}",
+                self.cwd.display(),
+                cwd.display()
+            )));
+        }
+
+        let errors: Vec<MassMoveError> = self
+            .pairs
+            .iter()
+            .map(|planned| (&planned.from, &planned.fingerprint))
+            .chain(self.duplicates.iter().flat_map(|planned| {
+                [
+                    (&planned.from, &planned.fingerprint),
+                    (&planned.to, &planned.target_fingerprint),
+                ]
+            }))
+            .filter_map(|(path, planned)| {
+                let reason = match file_system.metadata(path) {
+                    Err(_) => "not found",
+                    Ok(metadata) => {
+                        let fingerprint = Fingerprint::from_metadata(&metadata);
+                        if fingerprint.inode != planned.inode {
+                            "replaced"
+                        } else if fingerprint != *planned {
+                            "modified"
+                        } else {
+                            return None;
+                        }
+                    }
+                };
+                Some(MassMoveError::PlanDrift(format!(
+                    "{} is {}",
+                    path.display(),
+                    reason
+                )))
+            })
+            .collect();
+        MassMoveError::from_errors(errors)
+    }
+
+    /// Function returns pairs of files to move and duplicates after the drift check.
+    /// Duplicates are accepted only with the `delete-source` or `link` policy.
+    pub fn into_resolution(self, config: &Config) -> Result<Resolution, MassMoveError> {
+        if !self.duplicates.is_empty()
+            && !matches!(
+                self.on_duplicate,
+                Some(OnDuplicate::DeleteSource | OnDuplicate::Link)
+            )
+        {
+            return Err(MassMoveError::InvalidPlan(
+                "duplicates require on_duplicate delete-source or link".to_owned(),
+            ));
+        }
+        self.check_drift(config)?;
+        Ok(Resolution {
+            files_to_move: self
+                .pairs
+                .into_iter()
+                .map(|planned| MoveFiles {
+                    from: planned.from,
+                    to: planned.to,
+                })
+                .collect(),
+            duplicates: self
+                .duplicates
+                .into_iter()
+                .map(|planned| MoveFiles {
+                    from: planned.from,
+                    to: planned.to,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[test]
+fn test_check_drift() {
+    use std::fs;
+    use tempdir::TempDir;
+
+    let config = Config::new();
+    let directory = TempDir::new("mmv-plan").unwrap();
+    let file = directory.path().join("a.txt");
+    fs::write(&file, "content").unwrap();
+    let files_to_move = vec![MoveFiles {
+        from: file.clone(),
+        to: directory.path().join("b.txt"),
+    }];
+    let plan = Plan::new(&config, Vec::new(), None, &files_to_move).unwrap();
+
+    assert!(plan.check_drift(&config).is_ok());
+
+    fs::write(&file, "changed content").unwrap();
+    assert!(matches!(
+        plan.check_drift(&config),
+        Err(MassMoveError::PlanDrift(_))
+    ));
+
+    fs::remove_file(&file).unwrap();
+    assert!(matches!(
+        plan.check_drift(&config),
+        Err(MassMoveError::PlanDrift(_))
+    ));
+}
+
+#[test]
+fn test_plan_on_file_system() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    let fs = Arc::new(MemoryFileSystem::new());
+    fs.add_dir("/dir");
+    fs.add_file("/dir/a.txt", "content");
+    let config = Config::new().with_file_system(fs.clone());
+    let files_to_move = vec![MoveFiles::new("/dir/a.txt", "/dir/b.txt")];
+    let plan = Plan::new(&config, Vec::new(), None, &files_to_move).unwrap();
+    plan.write_to(&config, Path::new("/dir/plan.json")).unwrap();
+
+    let read = Plan::read_from(&config, Path::new("/dir/plan.json")).unwrap();
+    assert!(read.check_drift(&config).is_ok());
+
+    fs.add_file("/dir/a.txt", "changed content");
+    assert!(matches!(
+        read.check_drift(&config),
+        Err(MassMoveError::PlanDrift(_))
+    ));
+}
+
+#[test]
+fn test_into_resolution_duplicates() {
+    use super::file_system::MemoryFileSystem;
+    use std::sync::Arc;
+
+    let cases: Vec<(Option<OnDuplicate>, bool)> = vec![
+        (Some(OnDuplicate::DeleteSource), true),
+        (Some(OnDuplicate::Link), true),
+        (Some(OnDuplicate::Skip), false),
+        (None, false),
+    ];
+
+    for case in cases {
+        let fs = Arc::new(MemoryFileSystem::new());
+        fs.add_file("/a.txt", "same");
+        fs.add_file("/b.txt", "same");
+        let mut config = Config::new().with_file_system(fs);
+        if let Some(on_duplicate) = case.0 {
+            config = config.with_on_duplicate(on_duplicate);
+        }
+        let plan = Plan::new(&config, Vec::new(), None, &[])
+            .unwrap()
+            .with_duplicates(&config, &[MoveFiles::new("/a.txt", "/b.txt")])
+            .unwrap();
+        assert_eq!(
+            plan.into_resolution(&config).is_ok(),
+            case.1,
+            "policy: {:?}",
+            case.0
+        );
+    }
+}
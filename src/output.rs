@@ -0,0 +1,638 @@
+//! Module that reports results of moves.
+//! Results are printed as human readable text (`from -> to`) or as machine-readable records:
+//! a JSON document, NDJSON (one JSON object per line) or TSV.
+use crate::encoding;
+use crate::errors::MassMoveError;
+use crate::progress::Progress;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Maximal width of the aligned source column, longer names are not padded.
+const MAX_COLUMN_WIDTH: usize = 60;
+
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Format of the output, it is set with `--output`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Ndjson,
+    Tsv,
+}
+
+/// Amount of the text output, it is set with `-q` and `-v`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Verbosity {
+    /// Nothing is printed to stdout, errors are still printed to stderr.
+    Quiet,
+    /// Done (or planned) moves are printed, as `from -> to` lines in plain text.
+    #[default]
+    Normal,
+    /// Every pair is printed with its status and a summary line is printed at the end.
+    Verbose,
+}
+
+/// When the text output is colored, it is set with `--color`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ColorChoice {
+    /// Colored if stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Function returns true if the output should be colored.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal()
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// Status of a pair of files in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum MoveStatus {
+    /// The move is planned, but not done (`--dry-run`).
+    Planned,
+    Moved,
+    /// The move is not done because the batch is aborted.
+    Skipped,
+    Failed,
+    /// The move was done and then undone because the batch is aborted.
+    /// It follows the `Moved` record of the pair, the pair is not counted as moved in the summary.
+    RolledBack,
+    Deleted,
+    /// The source is replaced with a hard link to the target, a duplicate of it (`--on-duplicate link`).
+    Linked,
+    /// The source is planned to be deleted, the target is a duplicate of it (`--dry-run`).
+    PlannedDelete,
+    /// The source is planned to be replaced with a hard link to the target (`--dry-run`).
+    PlannedLink,
+}
+
+/// Record about one pair of files.
+#[derive(Serialize)]
+struct Record {
+    #[serde(rename = "type")]
+    record_type: &'static str,
+    status: MoveStatus,
+    from: String,
+    to: Option<String>,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+    /// Checksum of a file copied with `--verify`: `blake3:<hex>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+}
+
+/// Final record with counts of pairs per status.
+#[derive(Serialize, Default)]
+struct Summary {
+    #[serde(rename = "type")]
+    record_type: &'static str,
+    dry_run: bool,
+    planned: usize,
+    moved: usize,
+    skipped: usize,
+    failed: usize,
+    rolled_back: usize,
+    deleted: usize,
+    linked: usize,
+    /// Planned deletions and links of duplicates are counted in `planned` as well.
+    #[serde(skip)]
+    planned_delete: usize,
+    #[serde(skip)]
+    planned_link: usize,
+    error_kind: Option<&'static str>,
+    error: Option<String>,
+}
+
+/// JSON document printed in `json` format.
+#[derive(Serialize)]
+struct Document<'a> {
+    records: &'a [Record],
+    summary: &'a Summary,
+}
+
+struct ReporterState {
+    records: Vec<Record>,
+    summary: Summary,
+    /// Width of the source column of the colored and verbose text output.
+    width: usize,
+}
+
+/// `Reporter` prints results of moves in the chosen format.
+/// Text, NDJSON and TSV records are printed as soon as they are reported,
+/// JSON is printed as one document by `finish`.
+/// It is safe to report from several threads.
+/// Usage:
+/// ```rust
+/// use mmv::output::{MoveStatus, OutputFormat, Reporter};
+/// use std::path::Path;
+///
+/// let reporter = Reporter::new(OutputFormat::Ndjson, false);
+/// reporter.report(Path::new("a.txt"), Some(Path::new("b.txt")), MoveStatus::Moved, None);
+/// reporter.finish(None);
+/// assert_eq!(reporter.count(MoveStatus::Moved), 1);
+/// ```
+pub struct Reporter {
+    /// `None` for a silent reporter.
+    format: Option<OutputFormat>,
+    verbosity: Verbosity,
+    /// Colored text output, changed parts of names are highlighted.
+    color: bool,
+    /// Status line on stderr, it is hidden unless enabled with `with_progress`.
+    progress: Arc<Progress>,
+    state: Mutex<ReporterState>,
+}
+
+impl Reporter {
+    /// Construct a new `Reporter` for the output format.
+    pub fn new(format: OutputFormat, dry_run: bool) -> Self {
+        Self::with_format(Some(format), dry_run)
+    }
+
+    /// Construct a `Reporter` that only counts pairs and prints nothing, it is used by the library API.
+    pub fn silent(dry_run: bool) -> Self {
+        Self::with_format(None, dry_run)
+    }
+
+    fn with_format(format: Option<OutputFormat>, dry_run: bool) -> Self {
+        let summary = Summary {
+            record_type: "summary",
+            dry_run,
+            ..Summary::default()
+        };
+        Reporter {
+            format,
+            verbosity: Verbosity::Normal,
+            color: false,
+            progress: Arc::default(),
+            state: Mutex::new(ReporterState {
+                records: Vec::new(),
+                summary,
+                width: 0,
+            }),
+        }
+    }
+
+    /// Function sets the amount of the text output.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Function enables the colored text output.
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Function enables the progress status line on stderr.
+    pub fn with_progress(mut self, visible: bool) -> Self {
+        self.progress = Arc::new(Progress::new(visible));
+        self
+    }
+
+    /// Progress of the run, it is updated by matching and moving files.
+    pub fn progress(&self) -> &Arc<Progress> {
+        &self.progress
+    }
+
+    /// Function aligns the colored and verbose text output for a batch with these source files.
+    pub fn align<'a>(&self, sources: impl IntoIterator<Item = &'a Path>) {
+        let width = sources
+            .into_iter()
+            .map(|source| display_path(source).chars().count())
+            .filter(|&width| width <= MAX_COLUMN_WIDTH)
+            .max()
+            .unwrap_or(0);
+        let mut state = self.state.lock().unwrap();
+        state.width = state.width.max(width);
+    }
+
+    /// Function reports a status of a pair of files.
+    pub fn report(
+        &self,
+        from: &Path,
+        to: Option<&Path>,
+        status: MoveStatus,
+        error: Option<&MassMoveError>,
+    ) {
+        self.report_with_checksum(from, to, status, error, None);
+    }
+
+    /// Function reports a pair of files with the checksum of the verified copy,
+    /// the checksum is added to JSON and NDJSON records.
+    pub fn report_with_checksum(
+        &self,
+        from: &Path,
+        to: Option<&Path>,
+        status: MoveStatus,
+        error: Option<&MassMoveError>,
+        checksum: Option<&str>,
+    ) {
+        let record = Record {
+            record_type: "move",
+            status,
+            from: display_path(from),
+            to: to.map(display_path),
+            error_kind: error.map(MassMoveError::kind),
+            error: error.map(MassMoveError::display_chain),
+            checksum: checksum.map(str::to_owned),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let summary = &mut state.summary;
+        match status {
+            MoveStatus::Planned => summary.planned += 1,
+            MoveStatus::Moved => summary.moved += 1,
+            MoveStatus::Skipped => summary.skipped += 1,
+            MoveStatus::Failed => summary.failed += 1,
+            MoveStatus::RolledBack => {
+                summary.moved = summary.moved.saturating_sub(1);
+                summary.rolled_back += 1
+            }
+            MoveStatus::Deleted => summary.deleted += 1,
+            MoveStatus::Linked => summary.linked += 1,
+            MoveStatus::PlannedDelete => {
+                summary.planned += 1;
+                summary.planned_delete += 1
+            }
+            MoveStatus::PlannedLink => {
+                summary.planned += 1;
+                summary.planned_link += 1
+            }
+        }
+        if matches!(
+            status,
+            MoveStatus::Moved | MoveStatus::Skipped | MoveStatus::Failed
+        ) {
+            self.progress.done(1);
+        }
+
+        match self.format {
+            None => {}
+            Some(OutputFormat::Text) => {
+                if let Some(line) = self.format_text(&record, state.width) {
+                    self.progress.clear();
+                    println!("{}", line);
+                }
+            }
+            Some(OutputFormat::Json) => state.records.push(record),
+            Some(OutputFormat::Ndjson) => println!("{}", serde_json::to_string(&record).unwrap()),
+            Some(OutputFormat::Tsv) => println!(
+                "{}\t{}\t{}\t{}\t{}",
+                serde_json::to_value(record.status)
+                    .unwrap()
+                    .as_str()
+                    .unwrap(),
+                escape_tsv(encoding::os_str_bytes(from.as_os_str())),
+                escape_tsv(to.map_or(&[][..], |to| encoding::os_str_bytes(to.as_os_str()))),
+                record.error_kind.unwrap_or(""),
+                escape_tsv(record.error.as_deref().unwrap_or("").as_bytes()),
+            ),
+        }
+    }
+
+    /// Function returns the number of pairs with the status reported so far.
+    pub fn count(&self, status: MoveStatus) -> usize {
+        let state = self.state.lock().unwrap();
+        let summary = &state.summary;
+        match status {
+            MoveStatus::Planned => summary.planned,
+            MoveStatus::Moved => summary.moved,
+            MoveStatus::Skipped => summary.skipped,
+            MoveStatus::Failed => summary.failed,
+            MoveStatus::RolledBack => summary.rolled_back,
+            MoveStatus::Deleted => summary.deleted,
+            MoveStatus::Linked => summary.linked,
+            MoveStatus::PlannedDelete => summary.planned_delete,
+            MoveStatus::PlannedLink => summary.planned_link,
+        }
+    }
+
+    /// Function prints the summary (and all records in `json` format).
+    /// `error` is the error the run is finished with.
+    pub fn finish(&self, error: Option<&MassMoveError>) {
+        self.progress.clear();
+        let mut state = self.state.lock().unwrap();
+        state.summary.error_kind = error.map(MassMoveError::kind);
+        state.summary.error = error.map(MassMoveError::display_chain);
+
+        let summary = &state.summary;
+        match self.format {
+            Some(OutputFormat::Text)
+                if self.verbosity == Verbosity::Verbose
+                    || (self.verbosity == Verbosity::Normal && self.color) =>
+            {
+                println!("{}", format_summary(summary));
+            }
+            None | Some(OutputFormat::Text) => {}
+            Some(OutputFormat::Json) => {
+                let document = Document {
+                    records: &state.records,
+                    summary,
+                };
+                println!("{}", serde_json::to_string_pretty(&document).unwrap());
+            }
+            Some(OutputFormat::Ndjson) => {
+                println!("{}", serde_json::to_string(summary).unwrap())
+            }
+            Some(OutputFormat::Tsv) => println!(
+                "# planned={} moved={} skipped={} failed={} rolled_back={} deleted={} linked={}",
+                summary.planned,
+                summary.moved,
+                summary.skipped,
+                summary.failed,
+                summary.rolled_back,
+                summary.deleted,
+                summary.linked
+            ),
+        }
+    }
+}
+
+impl Reporter {
+    /// Function formats a record in the human readable format, `None` if it is not printed.
+    /// In normal mode only done (or planned) changes are printed, errors are printed to stderr
+    /// by `main`. In verbose mode every record is printed with its status.
+    /// A record without a target is a deletion. Source names are aligned in colored and
+    /// verbose modes only, plain `from -> to` lines are kept for scripts.
+    fn format_text(&self, record: &Record, width: usize) -> Option<String> {
+        let done = matches!(
+            (record.status, &record.to),
+            (
+                MoveStatus::Planned
+                    | MoveStatus::Moved
+                    | MoveStatus::PlannedDelete
+                    | MoveStatus::PlannedLink,
+                Some(_)
+            ) | (MoveStatus::Planned | MoveStatus::Deleted, None)
+        );
+        let show = match self.verbosity {
+            Verbosity::Quiet => false,
+            Verbosity::Normal => done,
+            Verbosity::Verbose => true,
+        };
+        if !show {
+            return None;
+        }
+
+        let to = record.to.as_deref().unwrap_or("(deleted)");
+        let mut line = if self.color {
+            format_changes(&record.from, to, width)
+        } else if self.verbosity == Verbosity::Verbose {
+            format!("{:<width$} -> {}", record.from, to)
+        } else {
+            format!("{} -> {}", record.from, to)
+        };
+        if self.verbosity == Verbosity::Verbose {
+            let label = format!("{:<11}", status_label(record.status));
+            let label = match (self.color, record.status) {
+                (false, _) => label,
+                (true, MoveStatus::Failed) => format!("{RED}{label}{RESET}"),
+                (true, MoveStatus::Skipped | MoveStatus::RolledBack) => {
+                    format!("{YELLOW}{label}{RESET}")
+                }
+                (true, _) => format!("{GREEN}{label}{RESET}"),
+            };
+            line = format!("{} {}", label, line);
+        } else if matches!(
+            record.status,
+            MoveStatus::PlannedDelete | MoveStatus::PlannedLink
+        ) {
+            line = format!("{} ({})", line, status_label(record.status));
+        }
+        Some(line)
+    }
+}
+
+/// Function returns the human readable name of a status.
+fn status_label(status: MoveStatus) -> &'static str {
+    match status {
+        MoveStatus::Planned => "planned",
+        MoveStatus::Moved => "moved",
+        MoveStatus::Skipped => "skipped",
+        MoveStatus::Failed => "failed",
+        MoveStatus::RolledBack => "rolled back",
+        MoveStatus::Deleted => "deleted",
+        MoveStatus::Linked => "linked",
+        MoveStatus::PlannedDelete => "to delete",
+        MoveStatus::PlannedLink => "to link",
+    }
+}
+
+/// Function formats the summary line of the text output, e.g. `2 moved, 1 skipped, 0 failed`.
+/// Counts of rolled back, deleted and linked files are added only if they are not zero.
+fn format_summary(summary: &Summary) -> String {
+    let mut counts = vec![
+        if summary.dry_run {
+            format!("{} planned", summary.planned)
+        } else {
+            format!("{} moved", summary.moved)
+        },
+        format!("{} skipped", summary.skipped),
+        format!("{} failed", summary.failed),
+    ];
+    if summary.rolled_back != 0 {
+        counts.push(format!("{} rolled back", summary.rolled_back));
+    }
+    if summary.deleted != 0 {
+        counts.push(format!("{} deleted", summary.deleted));
+    }
+    if summary.linked != 0 {
+        counts.push(format!("{} linked", summary.linked));
+    }
+    counts.join(", ")
+}
+
+/// Function splits a pair of names into the common prefix, the changed parts of both names
+/// and the common suffix. Names are split on character boundaries.
+fn split_changes<'a>(from: &'a str, to: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    let prefix = from
+        .char_indices()
+        .zip(to.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(from.len().min(to.len()), |((i, _), _)| i);
+    let (from_rest, to_rest) = (&from[prefix..], &to[prefix..]);
+
+    let suffix = from_rest
+        .char_indices()
+        .rev()
+        .zip(to_rest.chars().rev())
+        .take_while(|((_, a), b)| a == b)
+        .last()
+        .map_or(0, |((i, _), _)| from_rest.len() - i);
+    (
+        &from[..prefix],
+        &from_rest[..from_rest.len() - suffix],
+        &to_rest[..to_rest.len() - suffix],
+        &from_rest[from_rest.len() - suffix..],
+    )
+}
+
+/// Function formats a pair of names with the common parts dimmed and the changed parts
+/// highlighted, the source name is padded to the width of the column.
+fn format_changes(from: &str, to: &str, width: usize) -> String {
+    let (prefix, from_changed, to_changed, suffix) = split_changes(from, to);
+    let segment = |text: &str, color: &str| {
+        if text.is_empty() {
+            String::new()
+        } else {
+            format!("{color}{text}{RESET}")
+        }
+    };
+    let padding = width.saturating_sub(from.chars().count());
+    format!(
+        "{}{}{}{} -> {}{}{}",
+        segment(prefix, DIM),
+        segment(from_changed, RED),
+        segment(suffix, DIM),
+        " ".repeat(padding),
+        segment(prefix, DIM),
+        segment(to_changed, GREEN),
+        segment(suffix, DIM),
+    )
+}
+
+/// Function escapes characters that break TSV rows and writes invalid UTF-8 bytes as `\xNN`.
+fn escape_tsv(value: &[u8]) -> String {
+    let mut escaped = String::new();
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Function formats a path for the text and JSON output,
+/// invalid UTF-8 bytes are written as `\xNN` instead of being replaced.
+fn display_path(path: &Path) -> String {
+    encoding::escape_bytes(encoding::os_str_bytes(path.as_os_str()))
+}
+
+#[test]
+fn test_escape_tsv() {
+    let cases: Vec<(&[u8], &str)> = vec![
+        (b"file.txt", "file.txt"),
+        (b"a\tb", "a\\tb"),
+        (b"a\nb", "a\\nb"),
+        (b"a\rb", "a\\rb"),
+        (b"a\\b", "a\\\\b"),
+        (b"caf\xE9.txt", "caf\\xE9.txt"),
+        (b"a\\xE9", "a\\\\xE9"),
+    ];
+
+    for case in cases {
+        assert_eq!(escape_tsv(case.0), case.1, "value: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_split_changes() {
+    type Case<'a> = (&'a str, &'a str, (&'a str, &'a str, &'a str, &'a str));
+    let cases: Vec<Case> = vec![
+        ("notes/a.txt", "notes/b.txt", ("notes/", "a", "b", ".txt")),
+        ("a.txt", "a.md", ("a.", "txt", "md", "")),
+        ("abc", "xyz", ("", "abc", "xyz", "")),
+        ("file.txt", "file.txt", ("file.txt", "", "", "")),
+        ("aa", "aaa", ("aa", "", "a", "")),
+        ("фото-1.jpg", "фото-2.jpg", ("фото-", "1", "2", ".jpg")),
+        ("é.txt", "è.txt", ("", "é", "è", ".txt")),
+    ];
+
+    for case in cases {
+        assert_eq!(
+            split_changes(case.0, case.1),
+            case.2,
+            "pair: {} -> {}",
+            case.0,
+            case.1
+        );
+    }
+}
+
+#[test]
+fn test_format_text() {
+    type Case<'a> = (Verbosity, bool, MoveStatus, Option<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            Verbosity::Normal,
+            false,
+            MoveStatus::Moved,
+            Some("a.txt -> b.txt"),
+        ),
+        (Verbosity::Normal, false, MoveStatus::Skipped, None),
+        (Verbosity::Quiet, false, MoveStatus::Moved, None),
+        (
+            Verbosity::Verbose,
+            false,
+            MoveStatus::Skipped,
+            Some("skipped     a.txt     -> b.txt"),
+        ),
+        (
+            Verbosity::Normal,
+            true,
+            MoveStatus::Moved,
+            Some("\x1b[31ma\x1b[0m\x1b[2m.txt\x1b[0m     -> \x1b[32mb\x1b[0m\x1b[2m.txt\x1b[0m"),
+        ),
+    ];
+
+    for case in cases {
+        let reporter = Reporter::new(OutputFormat::Text, false)
+            .with_verbosity(case.0)
+            .with_color(case.1);
+        let record = Record {
+            record_type: "move",
+            status: case.2,
+            from: "a.txt".to_owned(),
+            to: Some("b.txt".to_owned()),
+            error_kind: None,
+            error: None,
+            checksum: None,
+        };
+        assert_eq!(
+            reporter.format_text(&record, 9).as_deref(),
+            case.3,
+            "verbosity: {:?}, color: {}, status: {:?}",
+            case.0,
+            case.1,
+            case.2
+        );
+    }
+}
@@ -1,16 +1,20 @@
 //! This module contains functions to insert matches (captutes) in target path.
+//! Target path and matches are handled as raw bytes, so names that are not valid UTF-8 are preserved.
+use crate::encoding::{os_str_bytes, os_string_from_bytes};
 use crate::errors::MassMoveError;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 
 /// Private function to insert match in filename.
 /// Function insert concrete match in filename and return it.
 /// If match not exist in matches or position is invalid, function return MassMoveError::InvalidTargetPath.
 fn insert_match_in_filename(
-    matches: &[String],
-    mut filename: String,
+    matches: &[OsString],
+    mut filename: Vec<u8>,
     i: usize,
-) -> Result<String, MassMoveError> {
+) -> Result<Vec<u8>, MassMoveError> {
     if i != 0 && i <= matches.len() {
-        filename.push_str(&matches[i - 1]);
+        filename.extend_from_slice(os_str_bytes(&matches[i - 1]));
         Ok(filename)
     } else {
         Err(MassMoveError::InvalidTargetPath(format!(
@@ -19,90 +23,166 @@ fn insert_match_in_filename(
     }
 }
 
+/// `Placeholder` is a named part of a target template: `#{name}` or `#{name:argument}`,
+/// e.g. `#{sha256:8}`. Placeholders are replaced with values of the matched file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placeholder<'a> {
+    pub name: &'a str,
+    pub argument: Option<&'a str>,
+}
+
+impl<'a> Placeholder<'a> {
+    /// Function parses the text between `#{` and `}`.
+    fn parse(text: &'a str) -> Self {
+        match text.split_once(':') {
+            Some((name, argument)) => Placeholder {
+                name,
+                argument: Some(argument),
+            },
+            None => Placeholder {
+                name: text,
+                argument: None,
+            },
+        }
+    }
+
+    /// Function builds an error of a placeholder with the reason.
+    pub fn error(&self, reason: &str) -> MassMoveError {
+        MassMoveError::InvalidTargetPath(format!("{} placeholder {}", reason, self))
+    }
+}
+
+impl fmt::Display for Placeholder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.argument {
+            Some(argument) => write!(f, "#{{{}:{}}}", self.name, argument),
+            None => write!(f, "#{{{}}}", self.name),
+        }
+    }
+}
+
 /// Public function to insert matches(captures) in target path.
+/// Placeholders `#{...}` are not supported, use `render_target` for them.
 /// Usage:
 /// ```rust
-/// let matches = vec!["v1".to_string(), "2024".to_string()];
-/// let target = "file-#1-#2.txt";
+/// use mmv::pattern::insert_matches_in_target;
+/// use std::ffi::{OsStr, OsString};
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let matches = vec![OsString::from("v1"), OsString::from("2024")];
+/// let target = OsStr::new("file-#1-#2.txt");
 /// let result = insert_matches_in_target(&matches, target)?;
 /// assert_eq!(result, "file-v1-2024.txt");
+/// # Ok(())
+/// # }
 /// ```
 pub fn insert_matches_in_target(
-    matches: &[String],
-    pattern: &str,
-) -> Result<String, MassMoveError> {
-    let mut new_filename = String::new();
+    matches: &[OsString],
+    pattern: &OsStr,
+) -> Result<OsString, MassMoveError> {
+    render_target(matches, pattern, &mut |placeholder| {
+        Err(placeholder.error("unknown"))
+    })
+}
 
-    let mut is_matching = false;
-    let mut match_index = 0;
+/// Public function to build a target path from a template: `#1`, `#2`, ... are replaced with
+/// matches, placeholders `#{name}` and `#{name:argument}` are replaced with values returned
+/// by `resolve`.
+/// Usage:
+/// ```rust
+/// use mmv::pattern::render_target;
+/// use std::ffi::{OsStr, OsString};
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let matches = vec![OsString::from("notes")];
+/// let result = render_target(&matches, OsStr::new("#1-#{year}.txt"), &mut |placeholder| {
+///     assert_eq!(placeholder.name, "year");
+///     Ok(OsString::from("2024"))
+/// })?;
+/// assert_eq!(result, "notes-2024.txt");
+/// # Ok(())
+/// # }
+/// ```
+pub fn render_target(
+    matches: &[OsString],
+    pattern: &OsStr,
+    resolve: &mut dyn FnMut(&Placeholder) -> Result<OsString, MassMoveError>,
+) -> Result<OsString, MassMoveError> {
+    let pattern = os_str_bytes(pattern);
+    let mut new_filename = Vec::new();
 
-    for c in pattern.chars() {
-        if c != '#' && !is_matching {
+    let mut position = 0;
+    while position < pattern.len() {
+        let c = pattern[position];
+        position += 1;
+        if c != b'#' {
             new_filename.push(c);
             continue;
         }
 
-        if is_matching && c.is_ascii_digit() {
-            match_index = match_index * 10 + c.to_digit(10).unwrap() as usize;
+        if pattern.get(position) == Some(&b'{') {
+            let Some(length) = pattern[position..].iter().position(|&c| c == b'}') else {
+                return Err(MassMoveError::InvalidTargetPath(String::from(
+                    "placeholder is not closed with '}'",
+                )));
+            };
+            let text =
+                std::str::from_utf8(&pattern[position + 1..position + length]).map_err(|_| {
+                    MassMoveError::InvalidTargetPath(String::from("placeholder is not valid UTF-8"))
+                })?;
+            let value = resolve(&Placeholder::parse(text))?;
+            new_filename.extend_from_slice(os_str_bytes(&value));
+            position += length + 1;
             continue;
         }
 
-        if c == '#' {
-            if is_matching {
-                new_filename = insert_match_in_filename(matches, new_filename, match_index)?;
-            }
-            is_matching = true;
-            match_index = 0;
-            continue;
-        }
-
-        if is_matching {
-            new_filename = insert_match_in_filename(matches, new_filename, match_index)?;
-            is_matching = false;
-            match_index = 0;
-        }
-
-        new_filename.push(c)
-    }
-
-    if is_matching {
+        let digits = pattern[position..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        // Only ASCII digits are taken, so the number is valid UTF-8; a too big number is not a match.
+        let match_index = std::str::from_utf8(&pattern[position..position + digits])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .unwrap_or(if digits == 0 { 0 } else { usize::MAX });
         new_filename = insert_match_in_filename(matches, new_filename, match_index)?;
+        position += digits;
     }
 
-    Ok(new_filename)
+    os_string_from_bytes(new_filename)
 }
 
 #[test]
 fn test_insert_matches_in_target_pattern() {
-    let cases: Vec<(&str, Vec<String>, &str, bool)> = vec![
+    let cases: Vec<(&str, Vec<OsString>, &str, bool)> = vec![
         (
             "file-#1-v1.txt",
-            vec!["1".to_string()],
+            vec![OsString::from("1")],
             "file-1-v1.txt",
             true,
         ),
         (
             "file#1.txt",
-            vec!["_match1".to_string()],
+            vec![OsString::from("_match1")],
             "file_match1.txt",
             true,
         ),
         (
             "file#1#2.txt",
-            vec!["_match1".to_string(), "_match2".to_string()],
+            vec![OsString::from("_match1"), OsString::from("_match2")],
             "file_match1_match2.txt",
             true,
         ),
         (
             "#1",
-            vec!["some_some.txt".to_string()],
+            vec![OsString::from("some_some.txt")],
             "some_some.txt",
             true,
         ),
-        ("file_#1.#2.txt", vec!["value1".to_string()], "", false),
+        ("file_#1.#2.txt", vec![OsString::from("value1")], "", false),
     ];
     for case in cases {
-        let result = insert_matches_in_target(&case.1, &case.0);
+        let result = insert_matches_in_target(&case.1, OsStr::new(case.0));
         assert_eq!(
             result.is_ok(),
             case.3,
@@ -111,8 +191,30 @@ fn test_insert_matches_in_target_pattern() {
             case.3,
             result.is_ok(),
         );
-        if result.is_ok() {
-            assert_eq!(result.unwrap(), case.2)
+        if let Ok(result) = result {
+            assert_eq!(result, case.2)
         }
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn test_insert_matches_in_target_non_utf8() {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    type Case<'a> = (&'a [u8], Vec<&'a [u8]>, &'a [u8]);
+    let cases: Vec<Case> = vec![
+        (b"file-#1.txt", vec![b"\xE9t\xE9"], b"file-\xE9t\xE9.txt"),
+        (b"\xFF-#1-#2", vec![b"a", b"\xFE"], b"\xFF-a-\xFE"),
+    ];
+
+    for case in cases {
+        let matches: Vec<OsString> = case
+            .1
+            .iter()
+            .map(|m| OsString::from_vec(m.to_vec()))
+            .collect();
+        let result = insert_matches_in_target(&matches, OsStr::from_bytes(case.0)).unwrap();
+        assert_eq!(result.as_bytes(), case.2, "pattern: {:?}", case.0);
+    }
+}
@@ -0,0 +1,424 @@
+//! Module resolves placeholders of target templates that depend on a matched file,
+//! e.g. `#{sha256:8}` is the first 8 hex digits of the SHA-256 hash of the file content,
+//! `#{date:%Y/%m}` is the capture date of an image and `#{artist}` is a tag of an audio file.
+use crate::errors::{MassMoveError, Operation};
+use crate::operations::audio::{read_audio_tags, AudioTags, AUDIO_FIELDS, NUMERIC_AUDIO_FIELDS};
+use crate::operations::exif::{read_exif_fields, ExifFields, EXIF_FIELDS};
+use crate::operations::file_system::FileSystem;
+use crate::operations::hash::{hash_file, HashAlgorithm};
+use crate::pattern::Placeholder;
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+
+/// Default format of `#{date}`.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// `FilePlaceholders` resolves placeholders of one matched file.
+/// Values are computed lazily on the first use and cached, so a file is hashed at most once
+/// per algorithm.
+/// Usage:
+/// ```rust
+/// use mmv::operations::file_system::MemoryFileSystem;
+/// use mmv::pattern::render_target;
+/// use mmv::placeholders::FilePlaceholders;
+/// use std::ffi::OsStr;
+/// use std::path::Path;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// let fs = MemoryFileSystem::new();
+/// fs.add_file("a.txt", "abc");
+/// let mut placeholders = FilePlaceholders::new(&fs, Path::new("a.txt"));
+/// let target = render_target(&[], OsStr::new("#{md5:6}.txt"), &mut |placeholder| {
+///     placeholders.resolve(placeholder)
+/// })?;
+/// assert_eq!(target, "900150.txt");
+/// # Ok(())
+/// # }
+/// ```
+pub struct FilePlaceholders<'a> {
+    file_system: &'a dyn FileSystem,
+    path: &'a Path,
+    hashes: HashMap<HashAlgorithm, String>,
+    /// EXIF fields, `Some(None)` if the file has no EXIF data.
+    exif: Option<Option<ExifFields>>,
+    /// Audio tags, `Some(None)` if the file has no tags.
+    audio: Option<Option<AudioTags>>,
+}
+
+impl<'a> FilePlaceholders<'a> {
+    /// Construct a new `FilePlaceholders` for the file.
+    pub fn new(file_system: &'a dyn FileSystem, path: &'a Path) -> Self {
+        FilePlaceholders {
+            file_system,
+            path,
+            hashes: HashMap::new(),
+            exif: None,
+            audio: None,
+        }
+    }
+
+    /// Function returns the value of the placeholder, unknown placeholders are errors.
+    pub fn resolve(&mut self, placeholder: &Placeholder) -> Result<OsString, MassMoveError> {
+        if let Some(algorithm) = HashAlgorithm::from_name(placeholder.name) {
+            return self.hash(algorithm, placeholder);
+        }
+        match placeholder.name {
+            "date" => self.date(placeholder),
+            name if EXIF_FIELDS.contains(&name) => self.exif_field(placeholder),
+            name if AUDIO_FIELDS.contains(&name) => self.audio_field(placeholder),
+            _ => Err(placeholder.error("unknown")),
+        }
+    }
+
+    /// Function returns the capture date of an image formatted with the argument
+    /// (`strftime` specifiers, `%Y-%m-%d` by default). Files without an EXIF date
+    /// fall back to the modification time.
+    fn date(&mut self, placeholder: &Placeholder) -> Result<OsString, MassMoveError> {
+        let exif_date = self.exif()?.and_then(|fields| fields.date_time);
+        let date_time = match exif_date {
+            Some(date_time) => date_time,
+            None => self.modified()?,
+        };
+
+        let mut value = String::new();
+        write!(
+            value,
+            "{}",
+            date_time.format(placeholder.argument.unwrap_or(DEFAULT_DATE_FORMAT))
+        )
+        .map_err(|_| placeholder.error("invalid format of"))?;
+        Ok(OsString::from(value))
+    }
+
+    /// Function returns the local modification time of the file.
+    fn modified(&self) -> Result<NaiveDateTime, MassMoveError> {
+        let metadata =
+            self.file_system
+                .metadata(self.path)
+                .map_err(|source| MassMoveError::Io {
+                    operation: Operation::ReadMetadata,
+                    path: self.path.to_owned(),
+                    source,
+                })?;
+        let modified = metadata.modified.ok_or_else(|| MassMoveError::Io {
+            operation: Operation::ReadMetadata,
+            path: self.path.to_owned(),
+            source: io::Error::from(io::ErrorKind::Unsupported),
+        })?;
+        Ok(DateTime::<Local>::from(modified).naive_local())
+    }
+
+    /// Function returns an EXIF field of an image, the argument is the value for images
+    /// without the field. Without the argument a missing field is an error.
+    fn exif_field(&mut self, placeholder: &Placeholder) -> Result<OsString, MassMoveError> {
+        let value = self
+            .exif()?
+            .and_then(|fields| fields.field(placeholder.name));
+        match (value, placeholder.argument) {
+            (Some(value), _) => Ok(OsString::from(sanitize_file_name(&value))),
+            (None, Some(default)) => Ok(OsString::from(default)),
+            (None, None) => Err(self.missing_error(placeholder, "EXIF field")),
+        }
+    }
+
+    /// Function returns an audio tag, values are sanitized to be valid file names.
+    /// The argument of numeric tags (`#{track:02}`) is the width of the zero-padded number,
+    /// the argument of text tags is the value for files without the tag.
+    fn audio_field(&mut self, placeholder: &Placeholder) -> Result<OsString, MassMoveError> {
+        let name = placeholder.name;
+        let value = self.audio()?.and_then(|tags| tags.field(name));
+        if NUMERIC_AUDIO_FIELDS.contains(&name) {
+            let width = match placeholder.argument {
+                None => 0,
+                Some(width) => width
+                    .parse()
+                    .map_err(|_| placeholder.error("invalid width of"))?,
+            };
+            return match value {
+                Some(value) => Ok(OsString::from(format!("{:0>width$}", value))),
+                None => Err(self.missing_error(placeholder, "tag")),
+            };
+        }
+        match (value, placeholder.argument) {
+            (Some(value), _) => Ok(OsString::from(sanitize_file_name(&value))),
+            (None, Some(default)) => Ok(OsString::from(default)),
+            (None, None) => Err(self.missing_error(placeholder, "tag")),
+        }
+    }
+
+    /// Function reads audio tags of the file once.
+    fn audio(&mut self) -> Result<Option<&AudioTags>, MassMoveError> {
+        if self.audio.is_none() {
+            let tags = read_audio_tags(self.file_system, self.path)
+                .map_err(|source| self.read_error(source))?;
+            self.audio = Some(tags);
+        }
+        Ok(self.audio.as_ref().and_then(Option::as_ref))
+    }
+
+    /// Function builds an error of a placeholder of a field the file does not have.
+    fn missing_error(&self, placeholder: &Placeholder, field: &str) -> MassMoveError {
+        placeholder.error(&format!("{} has no {} of", self.path.display(), field))
+    }
+
+    /// Function reads EXIF fields of the file once.
+    fn exif(&mut self) -> Result<Option<&ExifFields>, MassMoveError> {
+        if self.exif.is_none() {
+            let fields = read_exif_fields(self.file_system, self.path)
+                .map_err(|source| self.read_error(source))?;
+            self.exif = Some(fields);
+        }
+        Ok(self.exif.as_ref().and_then(Option::as_ref))
+    }
+
+    /// Function builds an error of reading the file.
+    fn read_error(&self, source: io::Error) -> MassMoveError {
+        MassMoveError::Io {
+            operation: Operation::ReadFile,
+            path: self.path.to_owned(),
+            source,
+        }
+    }
+
+    /// Function returns the hash of the file, an argument is the number of leading hex digits.
+    fn hash(
+        &mut self,
+        algorithm: HashAlgorithm,
+        placeholder: &Placeholder,
+    ) -> Result<OsString, MassMoveError> {
+        if !self.hashes.contains_key(&algorithm) {
+            let hash = hash_file(self.file_system, self.path, algorithm)
+                .map_err(|source| self.read_error(source))?;
+            self.hashes.insert(algorithm, hash);
+        }
+        let hash = &self.hashes[&algorithm];
+
+        let length = match placeholder.argument {
+            None => hash.len(),
+            Some(argument) => argument
+                .parse()
+                .ok()
+                .filter(|length| (1..=hash.len()).contains(length))
+                .ok_or_else(|| placeholder.error("invalid length of"))?,
+        };
+        Ok(OsString::from(&hash[..length]))
+    }
+}
+
+/// Function makes a tag value a valid file name: path separators and control characters
+/// are replaced with `_`, spaces and dots at the ends are removed.
+fn sanitize_file_name(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim_matches([' ', '.']);
+    if sanitized.is_empty() {
+        String::from("_")
+    } else {
+        sanitized.to_owned()
+    }
+}
+
+#[test]
+fn test_resolve_hash_placeholders() {
+    use crate::operations::file_system::MemoryFileSystem;
+    use crate::pattern::render_target;
+    use std::ffi::OsStr;
+
+    let cases: Vec<(&str, Option<&str>)> = vec![
+        (
+            "#{sha256}",
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        ),
+        ("#{sha256:8}.txt", Some("ba7816bf.txt")),
+        (
+            "#{md5:4}-#{md5}",
+            Some("9001-900150983cd24fb0d6963f7d28e17f72"),
+        ),
+        ("#{blake3:12}", Some("6437b3ac3846")),
+        ("#{md5:0}", None),
+        ("#{md5:33}", None),
+        ("#{md5:x}", None),
+        ("#{crc32}", None),
+        ("#{md5", None),
+    ];
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("a.txt", "abc");
+    for case in cases {
+        let mut placeholders = FilePlaceholders::new(&fs, Path::new("a.txt"));
+        let result = render_target(&[], OsStr::new(case.0), &mut |placeholder| {
+            placeholders.resolve(placeholder)
+        });
+        assert_eq!(
+            result.ok(),
+            case.1.map(OsString::from),
+            "template: {}",
+            case.0
+        );
+    }
+}
+
+#[test]
+fn test_resolve_exif_placeholders() {
+    use crate::operations::file_system::MemoryFileSystem;
+    use crate::pattern::render_target;
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+    use std::ffi::OsStr;
+    use std::io::Cursor;
+
+    let fields = [
+        Field {
+            tag: Tag::Model,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"EOS R5/II ".to_vec()]),
+        },
+        Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2024:10:08 17:30:05".to_vec()]),
+        },
+        Field {
+            tag: Tag::ImageWidth,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![640]),
+        },
+        Field {
+            tag: Tag::ImageLength,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![480]),
+        },
+    ];
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer.write(&mut tiff, true).unwrap();
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("photo.tif", tiff.into_inner());
+    fs.add_file("notes.txt", "no exif");
+    let modified = DateTime::<Local>::from(
+        fs.metadata(Path::new("notes.txt"))
+            .unwrap()
+            .modified
+            .unwrap(),
+    );
+
+    let mtime = modified.format("%Y-%m-%d").to_string();
+    let cases: Vec<(&str, &str, Option<&str>)> = vec![
+        ("photo.tif", "#{date}", Some("2024-10-08")),
+        (
+            "photo.tif",
+            "#{date:%Y/%m/%d_%H:%M:%S}",
+            Some("2024/10/08_17:30:05"),
+        ),
+        (
+            "photo.tif",
+            "#{camera}-#{width}x#{height}",
+            Some("EOS R5_II-640x480"),
+        ),
+        ("photo.tif", "#{lens:no-lens}", Some("no-lens")),
+        ("photo.tif", "#{lens}", None),
+        ("photo.tif", "#{date:%Q}", None),
+        ("notes.txt", "#{date}", Some(&mtime)),
+        ("notes.txt", "#{camera:unknown}", Some("unknown")),
+        ("notes.txt", "#{make}", None),
+    ];
+
+    for case in cases {
+        let mut placeholders = FilePlaceholders::new(&fs, Path::new(case.0));
+        let result = render_target(&[], OsStr::new(case.1), &mut |placeholder| {
+            placeholders.resolve(placeholder)
+        });
+        assert_eq!(
+            result.ok(),
+            case.2.map(OsString::from),
+            "file: {}, template: {}",
+            case.0,
+            case.1
+        );
+    }
+}
+
+#[test]
+fn test_sanitize_file_name() {
+    let cases: Vec<(&str, &str)> = vec![
+        ("Artist", "Artist"),
+        ("AC/DC", "AC_DC"),
+        (" Title. ", "Title"),
+        ("a\tb\\c", "a_b_c"),
+        ("..", "_"),
+        ("", "_"),
+    ];
+
+    for case in cases {
+        assert_eq!(sanitize_file_name(case.0), case.1, "value: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_resolve_audio_placeholders() {
+    use crate::operations::file_system::MemoryFileSystem;
+    use crate::pattern::render_target;
+    use lofty::config::WriteOptions;
+    use lofty::id3::v2::Id3v2Tag;
+    use lofty::prelude::*;
+    use std::ffi::OsStr;
+
+    let mut tag = Id3v2Tag::new();
+    tag.set_artist(String::from("AC/DC"));
+    tag.set_album(String::from("Back in Black"));
+    tag.set_title(String::from("What Do You Do for Money Honey?"));
+    tag.set_track(7);
+    let mut mp3 = Vec::new();
+    tag.dump_to(&mut mp3, WriteOptions::default()).unwrap();
+    // Two frames of MPEG-1 Layer III, 128 kbit/s, 44.1 kHz.
+    let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+    frame.resize(417, 0);
+    mp3.extend_from_slice(&frame);
+    mp3.extend_from_slice(&frame);
+
+    let fs = MemoryFileSystem::new();
+    fs.add_file("song.mp3", mp3);
+    fs.add_file("notes.txt", "no tags");
+
+    let cases: Vec<(&str, &str, Option<&str>)> = vec![
+        (
+            "song.mp3",
+            "#{artist}/#{album}/#{track:02} - #{title}.mp3",
+            Some("AC_DC/Back in Black/07 - What Do You Do for Money Honey?.mp3"),
+        ),
+        ("song.mp3", "#{track}-#{track:3}", Some("7-007")),
+        ("song.mp3", "#{genre:Unknown}", Some("Unknown")),
+        ("song.mp3", "#{year}", None),
+        ("song.mp3", "#{track:x}", None),
+        ("notes.txt", "#{artist:Various}", Some("Various")),
+        ("notes.txt", "#{title}", None),
+    ];
+
+    for case in cases {
+        let mut placeholders = FilePlaceholders::new(&fs, Path::new(case.0));
+        let result = render_target(&[], OsStr::new(case.1), &mut |placeholder| {
+            placeholders.resolve(placeholder)
+        });
+        assert_eq!(
+            result.ok(),
+            case.2.map(OsString::from),
+            "file: {}, template: {}",
+            case.0,
+            case.1
+        );
+    }
+}
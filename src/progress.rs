@@ -0,0 +1,176 @@
+//! Module that shows progress of long runs: a status line on stderr with counts of scanned,
+//! matched and moved files and bytes copied by moves across file systems.
+//! The line is redrawn at most every `REDRAW_INTERVAL` and is cleared before other output.
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minimal interval between two redraws of the status line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `Progress` counts files of a run and draws them in a status line on stderr.
+/// A hidden `Progress` only counts. It is safe to update from several threads.
+/// Usage:
+/// ```rust
+/// use mmv::progress::Progress;
+///
+/// let progress = Progress::new(false);
+/// progress.scanned(10);
+/// progress.matched(2);
+/// progress.set_total(2);
+/// progress.done(1);
+/// assert_eq!(progress.status_line(), "scanned 10, matched 2, moved 1/2");
+/// progress.clear();
+/// ```
+#[derive(Debug, Default)]
+pub struct Progress {
+    visible: bool,
+    scanned: AtomicUsize,
+    matched: AtomicUsize,
+    total: AtomicUsize,
+    done: AtomicUsize,
+    copied: AtomicU64,
+    drawn: Mutex<Drawn>,
+}
+
+/// `Drawn` is the state of the status line on stderr.
+#[derive(Debug, Default)]
+struct Drawn {
+    /// Time of the last redraw, it is kept when the line is cleared, so records printed
+    /// between updates do not make the line redraw more often.
+    time: Option<Instant>,
+    /// Whether the line is on the screen now.
+    shown: bool,
+}
+
+impl Progress {
+    /// Construct a new `Progress`, the status line is drawn only if `visible`.
+    pub fn new(visible: bool) -> Self {
+        Progress {
+            visible,
+            ..Progress::default()
+        }
+    }
+
+    /// Function counts entries of source directories.
+    pub fn scanned(&self, count: usize) {
+        self.scanned.fetch_add(count, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Function counts matched files.
+    pub fn matched(&self, count: usize) {
+        self.matched.fetch_add(count, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Function sets the number of pairs of the batch.
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Function counts pairs that are moved (or failed or skipped).
+    pub fn done(&self, count: usize) {
+        self.done.fetch_add(count, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Function counts bytes copied by moves across file systems.
+    pub fn copied(&self, bytes: u64) {
+        self.copied.fetch_add(bytes, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Function returns the text of the status line.
+    pub fn status_line(&self) -> String {
+        let mut line = format!(
+            "scanned {}, matched {}",
+            self.scanned.load(Ordering::Relaxed),
+            self.matched.load(Ordering::Relaxed)
+        );
+        let total = self.total.load(Ordering::Relaxed);
+        if total != 0 {
+            line.push_str(&format!(
+                ", moved {}/{}",
+                self.done.load(Ordering::Relaxed),
+                total
+            ));
+        }
+        let copied = self.copied.load(Ordering::Relaxed);
+        if copied != 0 {
+            line.push_str(&format!(", copied {}", format_bytes(copied)));
+        }
+        line
+    }
+
+    /// Function redraws the status line if the last redraw is old enough.
+    /// Threads do not wait for each other: if the line is being drawn, the update is skipped.
+    fn redraw(&self) {
+        if !self.visible {
+            return;
+        }
+        let Ok(mut drawn) = self.drawn.try_lock() else {
+            return;
+        };
+        if drawn
+            .time
+            .is_some_and(|time| time.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{}", self.status_line());
+        let _ = stderr.flush();
+        drawn.time = Some(Instant::now());
+        drawn.shown = true;
+    }
+
+    /// Function clears the status line, so other output is not mixed with it.
+    /// The line is drawn again on the next update after `REDRAW_INTERVAL`.
+    pub fn clear(&self) {
+        if !self.visible {
+            return;
+        }
+        let mut drawn = self.drawn.lock().unwrap();
+        if drawn.shown {
+            drawn.shown = false;
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// Function formats a number of bytes with a binary unit, e.g. `1.5 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[test]
+fn test_format_bytes() {
+    let cases: Vec<(u64, &str)> = vec![
+        (0, "0 B"),
+        (1023, "1023 B"),
+        (1024, "1.0 KiB"),
+        (1536, "1.5 KiB"),
+        (10 * 1024 * 1024, "10.0 MiB"),
+        (3 << 40, "3.0 TiB"),
+    ];
+
+    for case in cases {
+        assert_eq!(format_bytes(case.0), case.1, "bytes: {}", case.0);
+    }
+}
@@ -0,0 +1,339 @@
+//! Module provides the library API for mass renaming: `Rename` builds a plan from
+//! source and target patterns, `RenamePlan` validates the plan and executes it.
+//! The functions that plan moves for the command line are defined here too.
+use crate::config::Config;
+use crate::encoding;
+use crate::errors::MassMoveError;
+use crate::operations::conflict::ConflictResolver;
+use crate::operations::file_filter::FileFilter;
+use crate::operations::file_matcher::{FileMatcher, FileWithMatches};
+use crate::operations::file_move::{thread_pool, FilesMover, MoveFiles};
+use crate::output::{MoveStatus, Reporter};
+use crate::pattern::render_target;
+use crate::placeholders::FilePlaceholders;
+use clap::ValueEnum;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// `Rename` is a builder of a rename plan: pattern -> plan -> validate -> execute.
+/// Source patterns are paths with `*` in the file name, target patterns use `#1`, `#2`, ...
+/// for the parts matched by `*`. Pairs of files may also be added explicitly.
+/// Usage:
+/// ```rust
+/// use mmv::{Config, Rename};
+/// use std::fs::File;
+/// # use tempdir::TempDir;
+///
+/// # fn main() -> Result<(), mmv::MassMoveError> {
+/// # let directory = TempDir::new("mmv-doc")?;
+/// # let dir = directory.path().display();
+/// File::create(format!("{dir}/IMG_1.jpg"))?;
+///
+/// let plan = Rename::new()
+///     .config(Config::new().with_force_move(false))
+///     .pattern(format!("{dir}/IMG_*.jpg"), format!("{dir}/photo-#1.jpg"))
+///     .plan()?;
+/// assert_eq!(plan.pairs().len(), 1);
+///
+/// plan.validate()?;
+/// let moved = plan.execute()?;
+/// assert_eq!(moved, 1);
+/// assert!(directory.path().join("photo-1.jpg").exists());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Rename {
+    config: Config,
+    patterns: Vec<(PathBuf, OsString)>,
+    pairs: Vec<MoveFiles>,
+}
+
+/// Order of matched files of a source pattern, it is set with `--sort`.
+/// Files are planned, moved and printed in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum SortOrder {
+    /// Order of the directory listing.
+    #[default]
+    None,
+    /// Byte order of file paths.
+    Name,
+    /// Modification time, oldest first.
+    Mtime,
+}
+
+/// `RenamePlan` is a resolved batch of moves, it is built by `Rename::plan`.
+/// Files are not moved until `execute` is called.
+pub struct RenamePlan {
+    mover: FilesMover,
+}
+
+impl Default for Rename {
+    fn default() -> Self {
+        Rename::new()
+    }
+}
+
+impl Rename {
+    /// Construct a new `Rename` with the default `Config` and no patterns.
+    pub fn new() -> Self {
+        Rename {
+            config: Config::new(),
+            patterns: Vec::new(),
+            pairs: Vec::new(),
+        }
+    }
+
+    /// Function sets the configuration of the rename (force, filters, jobs, ...).
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Function adds a pair of source and target patterns.
+    pub fn pattern(mut self, source: impl Into<PathBuf>, target: impl Into<OsString>) -> Self {
+        self.patterns.push((source.into(), target.into()));
+        self
+    }
+
+    /// Function adds a pair of files to move as is.
+    pub fn pair(mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> Self {
+        self.pairs.push(MoveFiles::new(from, to));
+        self
+    }
+
+    /// Function resolves patterns into pairs of files, files are not moved.
+    /// With `Config::with_keep_going` all planning errors are aggregated.
+    pub fn plan(self) -> Result<RenamePlan, MassMoveError> {
+        let reporter = Reporter::silent(self.config.dry_run());
+        let mut errors = Vec::new();
+        let mut files_to_move = Vec::new();
+        for (source_path, target_path) in &self.patterns {
+            match plan_pair(
+                source_path,
+                target_path,
+                &self.config,
+                &reporter,
+                &mut errors,
+            ) {
+                Ok(planned) => files_to_move.extend(planned),
+                Err(err) => errors.push(err),
+            }
+        }
+        MassMoveError::from_errors(errors)?;
+
+        files_to_move.extend(self.pairs);
+        let resolution = ConflictResolver::new(&self.config)?.resolve(files_to_move, &reporter);
+        Ok(RenamePlan {
+            mover: FilesMover::new(self.config, resolution.files_to_move)
+                .with_duplicates(resolution.duplicates),
+        })
+    }
+}
+
+impl RenamePlan {
+    /// Function returns the planned pairs of files.
+    pub fn pairs(&self) -> &[MoveFiles] {
+        self.mover.files_to_move()
+    }
+
+    /// Function validates the plan: targets must not collide or replace existing files
+    /// (unless forced) and moves must not form a cycle.
+    pub fn validate(&self) -> Result<(), MassMoveError> {
+        self.mover.validate()?;
+        self.mover.schedule().map(|_| ())
+    }
+
+    /// Function moves files as one batch, if a move fails all done moves are rolled back.
+    /// It returns the number of moved files.
+    pub fn execute(self) -> Result<usize, MassMoveError> {
+        let reporter = Reporter::silent(false);
+        self.mover.run(&reporter)?;
+        Ok(reporter.count(MoveStatus::Moved))
+    }
+}
+
+/// Function builds a pair of files to move for a matched file.
+/// Placeholders `#{...}` of the target path are resolved from the matched file.
+pub(crate) fn plan_move(
+    target_path: &OsStr,
+    config: &Config,
+    file_with_match: &FileWithMatches,
+) -> Result<MoveFiles, MassMoveError> {
+    let mut placeholders =
+        FilePlaceholders::new(config.file_system().as_ref(), file_with_match.filename());
+    let target_filepath = PathBuf::from(render_target(
+        file_with_match.matches(),
+        target_path,
+        &mut |placeholder| placeholders.resolve(placeholder),
+    )?);
+
+    if config.strict_utf8() {
+        encoding::check_utf8(file_with_match.filename())?;
+        encoding::check_utf8(&target_filepath)?;
+    }
+
+    Ok(MoveFiles {
+        from: file_with_match.filename().to_owned(),
+        to: target_filepath,
+    })
+}
+
+/// Function builds a matcher with a filter stage for a source path.
+pub(crate) fn build_matcher(
+    source_path: &Path,
+    config: &Config,
+    reporter: &Reporter,
+) -> Result<FileMatcher, MassMoveError> {
+    let matcher = FileMatcher::from_source_path(source_path.to_owned())?;
+    let filter = FileFilter::from_config(config, matcher.source_directory())?;
+    Ok(matcher
+        .with_filter(filter)
+        .with_allow_no_match(config.allow_no_match())
+        .with_ignore_case(config.ignore_case())
+        .with_file_system(config.file_system().clone())
+        .with_progress(reporter.progress().clone()))
+}
+
+/// Function builds pairs of files to move for one pair of source and target paths,
+/// sorted in the order of `Config::sort_order`.
+/// With `--keep-going` files that can not be planned are reported as failed, their errors
+/// are collected in `errors` and other files are planned.
+pub(crate) fn plan_pair(
+    source_path: &Path,
+    target_path: &OsStr,
+    config: &Config,
+    reporter: &Reporter,
+    errors: &mut Vec<MassMoveError>,
+) -> Result<Vec<MoveFiles>, MassMoveError> {
+    let mut files_to_move = match_pair(source_path, target_path, config, reporter, errors)?;
+    match config.sort_order() {
+        SortOrder::None => {}
+        SortOrder::Name => files_to_move.sort_by(|a, b| a.from.cmp(&b.from)),
+        SortOrder::Mtime => {
+            let file_system = config.file_system();
+            files_to_move.sort_by_cached_key(|file_pair| {
+                let modified = file_system
+                    .metadata(&file_pair.from)
+                    .ok()
+                    .and_then(|metadata| metadata.modified);
+                (modified, file_pair.from.clone())
+            });
+        }
+    }
+    Ok(files_to_move)
+}
+
+/// Function matches files of one source path and builds pairs of files to move for them.
+fn match_pair(
+    source_path: &Path,
+    target_path: &OsStr,
+    config: &Config,
+    reporter: &Reporter,
+    errors: &mut Vec<MassMoveError>,
+) -> Result<Vec<MoveFiles>, MassMoveError> {
+    let matcher = build_matcher(source_path, config, reporter)?;
+    let pool = thread_pool(config)?;
+
+    if config.keep_going() {
+        let plan_file =
+            |file_with_match: Result<FileWithMatches, MassMoveError>| match file_with_match {
+                Ok(file_with_match) => (
+                    Some(file_with_match.filename().to_owned()),
+                    plan_move(target_path, config, &file_with_match),
+                ),
+                Err(err) => (None, Err(err)),
+            };
+        // Files are planned in parallel with `--jobs`, results keep the order of the listing.
+        let results: Vec<(Option<PathBuf>, Result<MoveFiles, MassMoveError>)> = match &pool {
+            Some(pool) => pool.install(|| {
+                matcher
+                    .par_match_results()
+                    .map(|files| files.into_par_iter().map(plan_file).collect())
+            })?,
+            None => matcher.iter_files_with_matches()?.map(plan_file).collect(),
+        };
+
+        let mut files_to_move = Vec::new();
+        for (filename, result) in results {
+            match result {
+                Ok(file_pair) => files_to_move.push(file_pair),
+                Err(err) => {
+                    if let Some(filename) = filename {
+                        reporter.report(&filename, None, MoveStatus::Failed, Some(&err));
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+        Ok(files_to_move)
+    } else if let Some(pool) = pool {
+        pool.install(|| {
+            let results: Vec<Result<MoveFiles, MassMoveError>> = matcher
+                .par_files_with_matches()?
+                .par_iter()
+                .map(|file_with_match| plan_move(target_path, config, file_with_match))
+                .collect();
+            MassMoveError::collect(results)
+        })
+    } else {
+        matcher
+            .iter_files_with_matches()?
+            .map(|file_with_match| plan_move(target_path, config, &file_with_match?))
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_plan_pair_keep_going() {
+    use crate::operations::file_system::MemoryFileSystem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::sync::Arc;
+
+    for jobs in [1, 4] {
+        let fs = Arc::new(MemoryFileSystem::new());
+        for file in [&b"a-1.txt"[..], b"a-2.txt", b"a-\xE9.txt", b"a-\xFF.txt"] {
+            fs.add_file(Path::new(OsStr::from_bytes(file)), "");
+        }
+        let config = Config::new()
+            .with_file_system(fs)
+            .with_keep_going(true)
+            .with_strict_utf8(true)
+            .with_jobs(jobs);
+        let reporter = Reporter::silent(true);
+        let mut errors = Vec::new();
+        let files_to_move = plan_pair(
+            Path::new("a-*.txt"),
+            OsStr::new("b-#1.txt"),
+            &config,
+            &reporter,
+            &mut errors,
+        )
+        .unwrap();
+
+        assert_eq!(
+            files_to_move,
+            vec![
+                MoveFiles::new("a-1.txt", "b-1.txt"),
+                MoveFiles::new("a-2.txt", "b-2.txt")
+            ],
+            "jobs: {}",
+            jobs
+        );
+        let errors: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "mmv: Invalid UTF-8 in path: a-\\xE9.txt",
+                "mmv: Invalid UTF-8 in path: a-\\xFF.txt"
+            ],
+            "jobs: {}",
+            jobs
+        );
+        assert_eq!(reporter.count(MoveStatus::Failed), 2, "jobs: {}", jobs);
+    }
+}
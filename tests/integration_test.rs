@@ -1,3 +1,10 @@
+// Baseline tests are kept as they are written, lints of newer clippy versions are allowed for them.
+#![allow(
+    clippy::expect_fun_call,
+    clippy::needless_borrow,
+    clippy::redundant_locals
+)]
+
 use assert_cmd::Command;
 use std::fs::File;
 use tempdir::TempDir;
@@ -129,3 +136,1284 @@ fn test_invalid_target_directory() {
         .failure()
         .stderr(expected_err);
 }
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_filename() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let file = OsString::from_vec(b"file-\xE9t\xE9.txt".to_vec());
+    let expected_file = OsString::from_vec(b"file-\xE9t\xE9-v1.txt".to_vec());
+
+    let file_path = source_dir.path().join(&file);
+    File::create(&file_path).expect("failed create non-utf8 file");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg(source_dir.path().join("file-*.txt"))
+        .arg(source_dir.path().join("file-#1-v1.txt"))
+        .assert()
+        .success();
+
+    assert!(!file_path.exists());
+    assert!(source_dir.path().join(expected_file).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_strict_utf8_rejects_invalid_filename() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let file_path = source_dir
+        .path()
+        .join(OsString::from_vec(b"file-\xE9.txt".to_vec()));
+    File::create(&file_path).expect("failed create non-utf8 file");
+
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+
+    let expected_err = format!(
+        "mmv: Invalid UTF-8 in path: {}/file-\\xE9.txt\n",
+        source_dir_path
+    );
+
+    cmd.arg("--strict-utf8")
+        .arg(format!("{}/file-*.txt", source_dir_path))
+        .arg(format!("{}/file-#1-v1.txt", source_dir_path))
+        .assert()
+        .failure()
+        .stderr(expected_err);
+    assert!(file_path.exists());
+}
+
+#[test]
+fn test_parallel_jobs() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    let files: Vec<String> = (0..20).map(|i| format!("file-{}.txt", i)).collect();
+    for file in &files {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    let output = cmd
+        .arg("--jobs")
+        .arg("4")
+        .arg(format!("{}/file-*.txt", source_dir_path))
+        .arg(format!("{}/file-#1-v1.txt", source_dir_path))
+        .output()
+        .expect("failed run mmv binary");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), files.len());
+    for i in 0..files.len() {
+        assert!(source_dir
+            .path()
+            .join(format!("file-{}-v1.txt", i))
+            .exists());
+    }
+}
+
+#[test]
+fn test_parallel_jobs_aggregates_errors() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a-1.txt", "a-2.txt", "b-1.txt", "b-2.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    let output = cmd
+        .arg("--jobs")
+        .arg("2")
+        .arg(format!("{}/a-*.txt", source_dir_path))
+        .arg(format!("{}/b-#1.txt", source_dir_path))
+        .output()
+        .expect("failed run mmv binary");
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for file in ["b-1.txt", "b-2.txt"] {
+        let expected_err = format!(
+            "mmv: Not able to replace existing file: {}/{}\n",
+            source_dir_path, file
+        );
+        assert!(stderr.contains(&expected_err), "stderr: {}", stderr);
+    }
+}
+
+#[test]
+fn test_exclude_patterns() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["file-1.txt", "file-2.tmp", "file-3.bak"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let expected_output = format!("{0}/file-1.txt -> {0}/moved-1.txt\n", source_dir_path);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--exclude")
+        .arg("*.tmp")
+        .arg("--exclude")
+        .arg("*.bak")
+        .arg(format!("{}/file-*.*", source_dir_path))
+        .arg(format!("{}/moved-#1.#2", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("file-2.tmp").exists());
+    assert!(source_dir.path().join("file-3.bak").exists());
+}
+
+#[test]
+fn test_ignore_files() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["file-1.txt", "file-2.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+    std::fs::write(source_dir.path().join(".mmvignore"), "file-2.txt\n")
+        .expect("failed create .mmvignore");
+
+    let expected_output = format!("{0}/file-1.txt -> {0}/moved-1.txt\n", source_dir_path);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--ignore-files")
+        .arg(format!("{}/file-*.txt", source_dir_path))
+        .arg(format!("{}/moved-#1.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("file-2.txt").exists());
+}
+
+#[test]
+fn test_metadata_filters() {
+    use std::time::{Duration, SystemTime};
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    std::fs::write(source_dir.path().join("log-big.txt"), vec![b'x'; 2048])
+        .expect("failed create log-big.txt");
+    std::fs::write(source_dir.path().join("log-small.txt"), b"x").expect("failed create");
+    std::fs::write(source_dir.path().join("log-old.txt"), vec![b'x'; 2048])
+        .expect("failed create log-old.txt");
+    File::options()
+        .write(true)
+        .open(source_dir.path().join("log-old.txt"))
+        .and_then(|file| {
+            file.set_modified(SystemTime::now() - Duration::from_secs(10 * 24 * 60 * 60))
+        })
+        .expect("failed set mtime of log-old.txt");
+
+    let expected_output = format!("{0}/log-old.txt -> {0}/archive-old.txt\n", source_dir_path);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--min-size")
+        .arg("1K")
+        .arg("--older-than")
+        .arg("7d")
+        .arg(format!("{}/log-*.txt", source_dir_path))
+        .arg(format!("{}/archive-#1.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("log-big.txt").exists());
+    assert!(source_dir.path().join("log-small.txt").exists());
+}
+
+#[test]
+fn test_multiple_pairs() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a-1.txt", "b-1.txt", "c-1.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg(format!("{}/a-*.txt", source_dir_path))
+        .arg(format!("{}/x-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/b-*.txt", source_dir_path))
+        .arg(format!("{}/y-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/c-*.txt", source_dir_path))
+        .arg(format!("{}/z-#1.txt", source_dir_path))
+        .assert()
+        .success();
+
+    for file in ["x-1.txt", "y-1.txt", "z-1.txt"] {
+        assert!(source_dir.path().join(file).exists(), "{} not moved", file);
+    }
+}
+
+#[test]
+fn test_multiple_pairs_swap() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    std::fs::write(source_dir.path().join("a.txt"), "a").expect("failed create a.txt");
+    std::fs::write(source_dir.path().join("b.txt"), "b").expect("failed create b.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--no-config")
+        .arg("-e")
+        .arg("a.*")
+        .arg("b.#1")
+        .arg("-e")
+        .arg("b.*")
+        .arg("a.#1")
+        .assert()
+        .success()
+        .stdout("a.txt -> b.txt\nb.txt -> a.txt\n");
+
+    let read = |file: &str| std::fs::read_to_string(source_dir.path().join(file)).unwrap();
+    assert_eq!(read("a.txt"), "b");
+    assert_eq!(read("b.txt"), "a");
+}
+
+#[test]
+fn test_multiple_pairs_collision() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a-1.txt", "b-1.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let expected_err = format!(
+        "mmv: Several files are moved to the same target: {0}/x-1.txt <- {0}/a-1.txt, {0}/b-1.txt\n",
+        source_dir_path
+    );
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("-e")
+        .arg(format!("{}/a-*.txt", source_dir_path))
+        .arg(format!("{}/x-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/b-*.txt", source_dir_path))
+        .arg(format!("{}/x-#1.txt", source_dir_path))
+        .assert()
+        .failure()
+        .stderr(expected_err);
+
+    assert!(source_dir.path().join("a-1.txt").exists());
+    assert!(source_dir.path().join("b-1.txt").exists());
+}
+
+#[test]
+fn test_multiple_pairs_rollback() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a-1.txt", "b-1.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+    std::fs::create_dir_all(source_dir.path().join("y-1").join("nested"))
+        .expect("failed create y-1 directory");
+
+    let expected_err = format!(
+        concat!(
+            "mmv: Failed move: {0}/b-1.txt -> {0}/y-1\n",
+            "  caused by: Is a directory (os error 21)\n",
+            "mmv: Rolled back 1 moved files\n"
+        ),
+        source_dir_path
+    );
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--force")
+        .arg("-e")
+        .arg(format!("{}/a-*.txt", source_dir_path))
+        .arg(format!("{}/x-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/b-*.txt", source_dir_path))
+        .arg(format!("{}/y-#1", source_dir_path))
+        .assert()
+        .failure()
+        .stderr(expected_err);
+
+    assert!(source_dir.path().join("a-1.txt").exists());
+    assert!(!source_dir.path().join("x-1.txt").exists());
+    assert!(source_dir.path().join("b-1.txt").exists());
+}
+
+#[test]
+fn test_pairs_from_stdin() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a.txt", "b.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let pair_list = format!(
+        "{0}/a.txt\t{0}/c.txt\n{0}/b.txt\t{0}/d.txt\n",
+        source_dir_path
+    );
+    let expected_output = format!(
+        "{0}/a.txt -> {0}/c.txt\n{0}/b.txt -> {0}/d.txt\n",
+        source_dir_path
+    );
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--from-file")
+        .arg("-")
+        .write_stdin(pair_list)
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("c.txt").exists());
+    assert!(source_dir.path().join("d.txt").exists());
+}
+
+#[test]
+fn test_nul_separated_pairs_from_file() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    File::create(source_dir.path().join("a\tb.txt")).expect("failed create a\\tb.txt");
+    let pair_list_path = source_dir.path().join("pairs.list");
+    std::fs::write(
+        &pair_list_path,
+        format!("{0}/a\tb.txt\0{0}/c.txt\0", source_dir_path),
+    )
+    .expect("failed create pairs.list");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("-0")
+        .arg("--from-file")
+        .arg(&pair_list_path)
+        .assert()
+        .success();
+
+    assert!(source_dir.path().join("c.txt").exists());
+}
+
+#[test]
+fn test_dry_run() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    File::create(source_dir.path().join("file-1.txt")).expect("failed create file-1.txt");
+
+    let expected_output = format!("{0}/file-1.txt -> {0}/file-1-v1.txt\n", source_dir_path);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--dry-run")
+        .arg(format!("{}/file-*.txt", source_dir_path))
+        .arg(format!("{}/file-#1-v1.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("file-1.txt").exists());
+    assert!(!source_dir.path().join("file-1-v1.txt").exists());
+}
+
+#[test]
+fn test_edit_with_scripted_editor() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["file-1.txt", "other.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let expected_output = format!("{0}/file-1.txt -> {0}/note-1.txt\n", source_dir_path);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--edit")
+        .arg("--editor")
+        .arg("sed -i s/file-/note-/")
+        .arg(format!("{}/*.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("note-1.txt").exists());
+    assert!(source_dir.path().join("other.txt").exists());
+}
+
+#[test]
+fn test_edit_deleted_line() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    File::create(source_dir.path().join("file-1.txt")).expect("failed create file-1.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--edit")
+        .arg("--editor")
+        .arg("sed -i s/.*//")
+        .arg(format!("{}/*.txt", source_dir_path))
+        .assert()
+        .failure()
+        .stderr(format!(
+            "mmv: Invalid edited list: line 1 is empty, use --allow-delete to delete {}/file-1.txt\n",
+            source_dir_path
+        ));
+    assert!(source_dir.path().join("file-1.txt").exists());
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--edit")
+        .arg("--allow-delete")
+        .arg("--editor")
+        .arg("sed -i s/.*//")
+        .arg(format!("{}/*.txt", source_dir_path))
+        .assert()
+        .success();
+    assert!(!source_dir.path().join("file-1.txt").exists());
+}
+
+#[test]
+fn test_edit_batch() {
+    type Case<'a> = (Vec<&'a str>, &'a str, bool, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        // The editor is not started without files.
+        (
+            vec!["--allow-no-match"],
+            "false",
+            true,
+            vec!["a.txt", "b.txt"],
+        ),
+        // A deleted name is taken by another file in the same batch.
+        (
+            vec!["--allow-delete"],
+            "sed -i -e s/.*a[.]txt$// -e s/b[.]txt$/a.txt/",
+            true,
+            vec!["a.txt"],
+        ),
+        (
+            vec!["--allow-delete", "--strict-utf8"],
+            "sed -i -e s/.*a[.]txt$// -e s/b[.]txt$/\\xff.txt/",
+            false,
+            vec!["a.txt", "b.txt"],
+        ),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        std::fs::write(source_dir.path().join("a.txt"), "a").expect("failed create: a.txt");
+        std::fs::write(source_dir.path().join("b.txt"), "b").expect("failed create: b.txt");
+        let pattern = if case.0.contains(&"--allow-no-match") {
+            "*.md"
+        } else {
+            "*.txt"
+        };
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let assert = cmd
+            .current_dir(source_dir.path())
+            .arg("--no-config")
+            .arg("--edit")
+            .args(&case.0)
+            .arg("--editor")
+            .arg(case.1)
+            .arg(pattern)
+            .assert();
+        if case.2 {
+            assert.success();
+        } else {
+            assert.failure();
+        }
+
+        let mut files: Vec<String> = std::fs::read_dir(source_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.3, "args: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_ndjson_output() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    File::create(source_dir.path().join("file-1.txt")).expect("failed create file-1.txt");
+
+    let expected_output = format!(
+        concat!(
+            r#"{{"type":"move","status":"planned","from":"{0}/file-1.txt","to":"{0}/file-1-v1.txt","error_kind":null,"error":null}}"#,
+            "\n",
+            r#"{{"type":"summary","dry_run":true,"planned":1,"moved":0,"skipped":0,"failed":0,"rolled_back":0,"deleted":0,"linked":0,"error_kind":null,"error":null}}"#,
+            "\n"
+        ),
+        source_dir_path
+    );
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--dry-run")
+        .arg("--output")
+        .arg("ndjson")
+        .arg(format!("{}/file-*.txt", source_dir_path))
+        .arg(format!("{}/file-#1-v1.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout(expected_output);
+
+    assert!(source_dir.path().join("file-1.txt").exists());
+}
+
+#[test]
+fn test_json_output_failure() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a.txt", "b.txt", "existing.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    let output = cmd
+        .arg("--output")
+        .arg("json")
+        .arg("-e")
+        .arg(format!("{}/a.txt", source_dir_path))
+        .arg(format!("{}/c.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/b.txt", source_dir_path))
+        .arg(format!("{}/existing.txt", source_dir_path))
+        .assert()
+        .failure()
+        .get_output()
+        .stdout
+        .clone();
+
+    let document: serde_json::Value =
+        serde_json::from_slice(&output).expect("output is not valid JSON");
+    let statuses: Vec<&str> = document["records"]
+        .as_array()
+        .expect("records are not an array")
+        .iter()
+        .map(|record| record["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, vec!["skipped", "skipped"]);
+    assert_eq!(document["summary"]["skipped"], 2);
+    assert_eq!(document["summary"]["error_kind"], "file_already_exists");
+    assert!(source_dir.path().join("a.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_machine_output_non_utf8_filename() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    File::create(
+        source_dir
+            .path()
+            .join(OsString::from_vec(b"file-\xE9.txt".to_vec())),
+    )
+    .expect("failed create non-utf8 file");
+
+    let expected_ndjson = format!(
+        r#"{{"type":"move","status":"planned","from":"{0}/file-\\xE9.txt","to":"{0}/file-\\xE9-v1.txt","error_kind":null,"error":null}}"#,
+        source_dir_path
+    );
+    let expected_tsv = format!(
+        "planned\t{0}/file-\\xE9.txt\t{0}/file-\\xE9-v1.txt\t\t",
+        source_dir_path
+    );
+
+    for (format, expected) in [("ndjson", expected_ndjson), ("tsv", expected_tsv)] {
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let output = cmd
+            .arg("--dry-run")
+            .arg("--output")
+            .arg(format)
+            .arg(format!("{}/file-*.txt", source_dir_path))
+            .arg(format!("{}/file-#1-v1.txt", source_dir_path))
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+
+        let output = String::from_utf8(output).expect("output is not valid UTF-8");
+        assert_eq!(
+            output.lines().next(),
+            Some(expected.as_str()),
+            "format: {}",
+            format
+        );
+    }
+}
+
+#[test]
+fn test_plan_out_and_apply() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    File::create(source_dir.path().join("file-1.txt")).expect("failed create file-1.txt");
+    File::create(source_dir.path().join("file-2.txt")).expect("failed create file-2.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--plan-out")
+        .arg("rename.plan.json")
+        .arg("file-*.txt")
+        .arg("note-#1.txt")
+        .assert()
+        .success();
+
+    assert!(source_dir.path().join("rename.plan.json").exists());
+    assert!(source_dir.path().join("file-1.txt").exists());
+    assert!(!source_dir.path().join("note-1.txt").exists());
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--apply")
+        .arg("rename.plan.json")
+        .assert()
+        .success();
+
+    assert!(source_dir.path().join("note-1.txt").exists());
+    assert!(source_dir.path().join("note-2.txt").exists());
+}
+
+#[test]
+fn test_apply_refuses_drifted_plan() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let file_path = source_dir.path().join("file-1.txt");
+    File::create(&file_path).expect("failed create file-1.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--plan-out")
+        .arg("rename.plan.json")
+        .arg("file-*.txt")
+        .arg("note-#1.txt")
+        .assert()
+        .success();
+
+    std::fs::write(&file_path, "changed").expect("failed write file-1.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--apply")
+        .arg("rename.plan.json")
+        .assert()
+        .failure()
+        .stderr("mmv: File system changed since the plan: file-1.txt is modified\n");
+
+    assert!(file_path.exists());
+    assert!(!source_dir.path().join("note-1.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_plan_out_rejects_non_utf8_pattern() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let file_path = source_dir
+        .path()
+        .join(OsString::from_vec(b"file-\xE9-1.txt".to_vec()));
+    File::create(&file_path).expect("failed create non-utf8 file");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--plan-out")
+        .arg("rename.plan.json")
+        .arg(OsString::from_vec(b"file-\xE9-*.txt".to_vec()))
+        .arg("note-#1.txt")
+        .assert()
+        .code(65)
+        .stderr("mmv: Invalid UTF-8 in path: file-\\xE9-*.txt\n");
+
+    assert!(file_path.exists());
+    assert!(!source_dir.path().join("rename.plan.json").exists());
+}
+
+#[test]
+fn test_keep_going() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+
+    for file in ["a-1.txt", "a-2.txt", "b-1.txt", "existing.txt"] {
+        File::create(source_dir.path().join(file))
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let expected_err = format!(
+        concat!(
+            "mmv: Files for pattern 'c-*.txt' not found\n",
+            "mmv: Not able to replace existing file: {0}/existing.txt\n",
+            "mmv: Kept going after 2 errors, 2 files moved\n"
+        ),
+        source_dir_path
+    );
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--keep-going")
+        .arg("-e")
+        .arg(format!("{}/a-*.txt", source_dir_path))
+        .arg(format!("{}/x-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/c-*.txt", source_dir_path))
+        .arg(format!("{}/y-#1.txt", source_dir_path))
+        .arg("-e")
+        .arg(format!("{}/b-*.txt", source_dir_path))
+        .arg(format!("{}/existing.txt", source_dir_path))
+        .assert()
+        .code(2)
+        .stderr(expected_err);
+
+    assert!(source_dir.path().join("x-1.txt").exists());
+    assert!(source_dir.path().join("x-2.txt").exists());
+    assert!(source_dir.path().join("b-1.txt").exists());
+}
+
+#[test]
+fn test_exit_codes() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let source_dir_path = source_dir.path().to_str().unwrap();
+    File::create(source_dir.path().join("a.txt")).expect("failed create a.txt");
+    File::create(source_dir.path().join("b.txt")).expect("failed create b.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg(format!("{}/c-*.txt", source_dir_path))
+        .arg(format!("{}/d-#1.txt", source_dir_path))
+        .assert()
+        .code(1);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--allow-no-match")
+        .arg(format!("{}/c-*.txt", source_dir_path))
+        .arg(format!("{}/d-#1.txt", source_dir_path))
+        .assert()
+        .success()
+        .stdout("");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg(format!("{}/a.txt", source_dir_path))
+        .arg(format!("{}/b.txt", source_dir_path))
+        .assert()
+        .code(73);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg(format!("{}/a.txt", source_dir_path))
+        .assert()
+        .code(64);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.arg("--no-such-flag").assert().code(64);
+}
+
+#[test]
+fn test_config_file_and_preset() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let config_dir = source_dir.path().join("config").join("mmv");
+    std::fs::create_dir_all(&config_dir).expect("failed create config dir");
+    std::fs::create_dir(source_dir.path().join("photos")).expect("failed create photos");
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[presets.photos]\ntarget = \"photos/IMG-#1.jpg\"\n",
+    )
+    .expect("failed write config.toml");
+    std::fs::write(
+        source_dir.path().join(".mmv.toml"),
+        "ignore-case = true\nsort = \"name\"\n",
+    )
+    .expect("failed write .mmv.toml");
+    for file in ["img_1.JPG", "IMG_2.jpg", "IMG_3.png"] {
+        File::create(source_dir.path().join(file)).expect("failed create file");
+    }
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .env("XDG_CONFIG_HOME", source_dir.path().join("config"))
+        .arg("--dry-run")
+        .arg("--preset")
+        .arg("photos")
+        .arg("IMG_*.jpg")
+        .assert()
+        .success()
+        .stdout("IMG_2.jpg -> photos/IMG-2.jpg\nimg_1.JPG -> photos/IMG-1.jpg\n");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .env("XDG_CONFIG_HOME", source_dir.path().join("config"))
+        .arg("--no-config")
+        .arg("--dry-run")
+        .arg("IMG_*.jpg")
+        .arg("photos/IMG-#1.jpg")
+        .assert()
+        .success()
+        .stdout("IMG_2.jpg -> photos/IMG-2.jpg\n");
+
+    std::fs::write(source_dir.path().join(".mmv.toml"), "ignore-cases = true\n")
+        .expect("failed write .mmv.toml");
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .env("XDG_CONFIG_HOME", source_dir.path().join("config"))
+        .arg("IMG_*.jpg")
+        .arg("photos/IMG-#1.jpg")
+        .assert()
+        .code(78)
+        .stderr(format!(
+            "mmv: Invalid config file: {}: unknown key `ignore-cases`\n",
+            source_dir.path().join(".mmv.toml").display()
+        ));
+}
+
+#[test]
+fn test_no_flags_override_config_file() {
+    type Case<'a> = (Vec<&'a str>, bool, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (vec![], true, vec!["b.txt", "config.toml"]),
+        (
+            vec!["--no-force"],
+            false,
+            vec!["a.txt", "b.txt", "config.toml"],
+        ),
+        (
+            vec!["--no-force", "--force"],
+            true,
+            vec!["b.txt", "config.toml"],
+        ),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        std::fs::write(source_dir.path().join("config.toml"), "force = true\n")
+            .expect("failed write config.toml");
+        for file in ["a.txt", "b.txt"] {
+            File::create(source_dir.path().join(file))
+                .unwrap_or_else(|_| panic!("failed create: {}", file));
+        }
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let assert = cmd
+            .current_dir(source_dir.path())
+            .arg("--config")
+            .arg("config.toml")
+            .args(&case.0)
+            .arg("a.txt")
+            .arg("b.txt")
+            .assert();
+        if case.1 {
+            assert.success();
+        } else {
+            assert.code(73);
+        }
+
+        let mut files: Vec<String> = std::fs::read_dir(source_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.2, "args: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_project_config_rejects_editor() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    std::fs::write(
+        source_dir.path().join(".mmv.toml"),
+        "editor = \"touch pwned\"\n",
+    )
+    .expect("failed write .mmv.toml");
+    File::create(source_dir.path().join("a.txt")).expect("failed create a.txt");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .env("XDG_CONFIG_HOME", source_dir.path().join("config"))
+        .arg("--edit")
+        .arg("*.txt")
+        .assert()
+        .code(78)
+        .stderr(format!(
+            "mmv: Invalid config file: {}: `editor` is allowed only in the user config file\n",
+            source_dir.path().join(".mmv.toml").display()
+        ));
+    assert!(!source_dir.path().join("pwned").exists());
+}
+
+#[test]
+fn test_completions_and_man_page() {
+    let cases: Vec<(Vec<&str>, &str)> = vec![
+        (vec!["completions", "fish"], "complete -c mmv"),
+        (vec!["completions", "bash"], "_mmv()"),
+        (vec!["man"], ".SH \"EXIT STATUS\""),
+    ];
+
+    for case in cases {
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let output = cmd.args(&case.0).assert().success().get_output().clone();
+        let stdout = String::from_utf8(output.stdout).expect("output is not UTF-8");
+        assert!(stdout.contains(case.1), "args: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_verbosity_and_color() {
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    File::create(source_dir.path().join("a.txt")).expect("failed create a.txt");
+    File::create(source_dir.path().join("bb.txt")).expect("failed create bb.txt");
+
+    type Case<'a> = (Vec<&'a str>, &'a str);
+    let cases: Vec<Case> = vec![
+        (vec!["-q"], ""),
+        (
+            vec!["--no-progress", "--sort", "name"],
+            "a.txt -> a.md\nbb.txt -> bb.md\n",
+        ),
+        (
+            vec!["-v", "--sort", "name"],
+            "planned     a.txt  -> a.md\nplanned     bb.txt -> bb.md\n2 planned, 0 skipped, 0 failed\n",
+        ),
+        (
+            vec!["--color", "always", "--sort", "name"],
+            "\x1b[2ma.\x1b[0m\x1b[31mtxt\x1b[0m  -> \x1b[2ma.\x1b[0m\x1b[32mmd\x1b[0m\n\
+             \x1b[2mbb.\x1b[0m\x1b[31mtxt\x1b[0m -> \x1b[2mbb.\x1b[0m\x1b[32mmd\x1b[0m\n\
+             2 planned, 0 skipped, 0 failed\n",
+        ),
+    ];
+
+    for case in cases {
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        cmd.current_dir(source_dir.path())
+            .arg("--no-config")
+            .arg("--dry-run")
+            .args(&case.0)
+            .arg("*.txt")
+            .arg("#1.md")
+            .assert()
+            .success()
+            .stdout(case.1)
+            .stderr("");
+    }
+}
+
+#[test]
+fn test_on_conflict() {
+    type Case<'a> = (Vec<&'a str>, &'a str, &'a str, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            vec!["--on-conflict", "suffix"],
+            "*.log",
+            "a.log -> x (1).txt\nb.log -> x (2).txt\n",
+            vec!["x (1).txt", "x (2).txt", "x.txt"],
+        ),
+        (
+            vec!["--on-conflict", "suffix", "--suffix-format", "-{n}"],
+            "*.log",
+            "a.log -> x-1.txt\nb.log -> x-2.txt\n",
+            vec!["x-1.txt", "x-2.txt", "x.txt"],
+        ),
+        (
+            vec!["--on-conflict", "skip", "-v"],
+            "*.log",
+            "skipped     a.log -> x.txt\nskipped     b.log -> x.txt\n0 moved, 2 skipped, 0 failed\n",
+            vec!["a.log", "b.log", "x.txt"],
+        ),
+        (
+            vec!["--on-conflict", "backup"],
+            "a.*",
+            "x.txt -> x.txt~\na.log -> x.txt\n",
+            vec!["b.log", "x.txt", "x.txt~"],
+        ),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        for file in ["a.log", "b.log", "x.txt"] {
+            File::create(source_dir.path().join(file))
+                .unwrap_or_else(|_| panic!("failed create: {}", file));
+        }
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        cmd.current_dir(source_dir.path())
+            .arg("--no-config")
+            .arg("--sort")
+            .arg("name")
+            .args(&case.0)
+            .arg(case.1)
+            .arg("x.txt")
+            .assert()
+            .success()
+            .stdout(case.2);
+
+        let mut files: Vec<String> = std::fs::read_dir(source_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.3, "args: {:?}", case.0);
+    }
+}
+
+#[test]
+fn test_hash_placeholders() {
+    type Case<'a> = (&'a str, Option<&'a str>, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            "#{sha256:8}.#1",
+            Some("a.txt -> ba7816bf.txt\n"),
+            vec!["ba7816bf.txt"],
+        ),
+        (
+            "#{md5:6}-#{blake3:4}.#1",
+            Some("a.txt -> 900150-6437.txt\n"),
+            vec!["900150-6437.txt"],
+        ),
+        ("#{crc32}.#1", None, vec!["a.txt"]),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        std::fs::write(source_dir.path().join("a.txt"), "abc").expect("failed create: a.txt");
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let assert = cmd
+            .current_dir(source_dir.path())
+            .arg("--no-config")
+            .arg("--verify")
+            .arg("a.*")
+            .arg(case.0)
+            .assert();
+        match case.1 {
+            Some(stdout) => assert.success().stdout(stdout),
+            None => assert
+                .code(64)
+                .stderr("mmv: Invalid target path: unknown placeholder #{crc32}\n"),
+        };
+
+        let mut files: Vec<String> = std::fs::read_dir(source_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.2, "target: {}", case.0);
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_on_duplicate() {
+    use std::os::unix::fs::MetadataExt;
+
+    type Case<'a> = (&'a str, &'a str, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        (
+            "skip",
+            "skipped     a.jpg -> photos/a.jpg\nmoved       b.jpg -> photos/b.jpg\n1 moved, 1 skipped, 0 failed\n",
+            vec!["a.jpg", "photos"],
+        ),
+        (
+            "delete-source",
+            "moved       b.jpg -> photos/b.jpg\ndeleted     a.jpg -> photos/a.jpg\n1 moved, 0 skipped, 0 failed, 1 deleted\n",
+            vec!["photos"],
+        ),
+        (
+            "link",
+            "moved       b.jpg -> photos/b.jpg\nlinked      a.jpg -> photos/a.jpg\n1 moved, 0 skipped, 0 failed, 1 linked\n",
+            vec!["a.jpg", "photos"],
+        ),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        let path = source_dir.path();
+        std::fs::create_dir(path.join("photos")).expect("failed create: photos");
+        std::fs::write(path.join("a.jpg"), "same").expect("failed create: a.jpg");
+        std::fs::write(path.join("photos/a.jpg"), "same").expect("failed create: photos/a.jpg");
+        std::fs::write(path.join("b.jpg"), "other").expect("failed create: b.jpg");
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        cmd.current_dir(path)
+            .arg("--no-config")
+            .arg("--sort")
+            .arg("name")
+            .arg("-v")
+            .arg("--on-duplicate")
+            .arg(case.0)
+            .arg("*.jpg")
+            .arg("photos/#1.jpg")
+            .assert()
+            .success()
+            .stdout(case.1);
+
+        let mut files: Vec<String> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.2, "policy: {}", case.0);
+        let links = std::fs::metadata(path.join("photos/a.jpg"))
+            .unwrap()
+            .nlink();
+        assert_eq!(links, 1 + u64::from(case.0 == "link"), "policy: {}", case.0);
+    }
+}
+
+#[test]
+fn test_plan_out_and_apply_duplicates() {
+    type Case<'a> = (&'a str, bool, Vec<&'a str>);
+    let cases: Vec<Case> = vec![
+        ("delete-source", false, vec!["photos", "rename.plan.json"]),
+        ("link", false, vec!["a.jpg", "photos", "rename.plan.json"]),
+        (
+            "delete-source",
+            true,
+            vec!["a.jpg", "b.jpg", "photos", "rename.plan.json"],
+        ),
+    ];
+
+    for case in cases {
+        let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+        let path = source_dir.path();
+        std::fs::create_dir(path.join("photos")).expect("failed create: photos");
+        std::fs::write(path.join("a.jpg"), "same").expect("failed create: a.jpg");
+        std::fs::write(path.join("photos/a.jpg"), "same").expect("failed create: photos/a.jpg");
+        std::fs::write(path.join("b.jpg"), "other").expect("failed create: b.jpg");
+
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        cmd.current_dir(path)
+            .arg("--no-config")
+            .arg("--sort")
+            .arg("name")
+            .arg("-v")
+            .arg("--on-duplicate")
+            .arg(case.0)
+            .arg("--plan-out")
+            .arg("rename.plan.json")
+            .arg("*.jpg")
+            .arg("photos/#1.jpg")
+            .assert()
+            .success()
+            .stdout(format!(
+                "planned     b.jpg -> photos/b.jpg\n{:<11} a.jpg -> photos/a.jpg\n2 planned, 0 skipped, 0 failed\n",
+                if case.0 == "link" { "to link" } else { "to delete" }
+            ));
+        assert!(path.join("a.jpg").exists(), "policy: {}", case.0);
+
+        if case.1 {
+            std::fs::write(path.join("photos/a.jpg"), "changed")
+                .expect("failed write: photos/a.jpg");
+        }
+        let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+        let assert = cmd
+            .current_dir(path)
+            .arg("--no-config")
+            .arg("--apply")
+            .arg("rename.plan.json")
+            .assert();
+        if case.1 {
+            assert
+                .failure()
+                .stderr("mmv: File system changed since the plan: photos/a.jpg is modified\n");
+        } else {
+            assert.success();
+        }
+
+        let mut files: Vec<String> = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, case.2, "policy: {}, changed: {}", case.0, case.1);
+    }
+}
+
+#[test]
+fn test_date_placeholder_falls_back_to_mtime() {
+    use std::time::{Duration, SystemTime};
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    let file = File::create(source_dir.path().join("IMG_1.jpg")).expect("failed create: IMG_1.jpg");
+    // 2020-06-15 12:00:00 UTC, the same day in every time zone.
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_592_222_400))
+        .expect("failed set mtime: IMG_1.jpg");
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--no-config")
+        .arg("IMG_*.jpg")
+        .arg("#{date:%Y%m%d}-#{camera:unknown}-#1.jpg")
+        .assert()
+        .success()
+        .stdout("IMG_1.jpg -> 20200615-unknown-1.jpg\n");
+    assert!(source_dir.path().join("20200615-unknown-1.jpg").exists());
+}
+
+#[test]
+fn test_audio_placeholders_with_create_dirs() {
+    use lofty::config::WriteOptions;
+    use lofty::id3::v2::Id3v2Tag;
+    use lofty::prelude::*;
+
+    let source_dir = TempDir::new("test_dir").expect("failed to create test_dir");
+    for (file, track, title) in [("a.mp3", 1, "Hells Bells"), ("b.mp3", 2, "Shoot to Thrill")] {
+        let mut tag = Id3v2Tag::new();
+        tag.set_artist(String::from("AC/DC"));
+        tag.set_album(String::from("Back in Black"));
+        tag.set_title(String::from(title));
+        tag.set_track(track);
+        let mut mp3 = Vec::new();
+        tag.dump_to(&mut mp3, WriteOptions::default()).unwrap();
+        // Two frames of MPEG-1 Layer III, 128 kbit/s, 44.1 kHz.
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.resize(417, 0);
+        mp3.extend_from_slice(&frame);
+        mp3.extend_from_slice(&frame);
+        std::fs::write(source_dir.path().join(file), mp3)
+            .unwrap_or_else(|_| panic!("failed create: {}", file));
+    }
+
+    let target = "#{artist}/#{album}/#{track:02} - #{title}.mp3";
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--no-config")
+        .arg("--sort")
+        .arg("name")
+        .arg("*.mp3")
+        .arg(target)
+        .assert()
+        .code(66);
+
+    let mut cmd = Command::cargo_bin("mmv").expect("failed run mmv binary");
+    cmd.current_dir(source_dir.path())
+        .arg("--no-config")
+        .arg("--sort")
+        .arg("name")
+        .arg("-p")
+        .arg("*.mp3")
+        .arg(target)
+        .assert()
+        .success()
+        .stdout(
+            "a.mp3 -> AC_DC/Back in Black/01 - Hells Bells.mp3\n\
+             b.mp3 -> AC_DC/Back in Black/02 - Shoot to Thrill.mp3\n",
+        );
+    let album = source_dir.path().join("AC_DC/Back in Black");
+    assert!(album.join("01 - Hells Bells.mp3").exists());
+    assert!(album.join("02 - Shoot to Thrill.mp3").exists());
+}